@@ -6,6 +6,15 @@
 
 namespace s2t {
 
+/// Axis of the model pointing upwards
+enum class UpAxis {
+  Z,
+  Y,
+  X,
+  /// guessed from the format and the geometry
+  Auto,
+};
+
 struct PictureBuffer {
   /// data in rgba8888 format
   const uint8_t *data;
@@ -28,12 +37,29 @@ struct RenderSettings {
   uint64_t timeout;
 };
 
+/// Options in addition to the RenderSettings, new ones are appended
+/// Set size to sizeof(RenderOptions), the options past the size the caller was built with keep their defaults.
+struct RenderOptions {
+  /// size of the struct in bytes
+  uint32_t size;
+  /// turn flipped faces and inside out models around
+  bool repair;
+  /// rotated onto the z axis
+  UpAxis up_axis;
+};
+
 extern "C" {
 
 /// Renders a mesh to a picture
 /// Free the buffer with free_picture_buffer
 PictureBuffer render(const char *path, RenderSettings settings);
 
+/// Renders a mesh to a picture, options may be null
+/// Free the buffer with free_picture_buffer
+PictureBuffer render_with_options(const char *path,
+                                  RenderSettings settings,
+                                  const RenderOptions *options);
+
 /// Frees the memory of a PictureBuffer
 void free_picture_buffer(PictureBuffer buffer);
 
@@ -12,22 +12,40 @@ impl AABB {
     }
 
     pub fn from_iterable(mesh: impl IntoIterator<Item = Triangle> + Copy) -> Self {
-        let mut lower = Vec3::new(f32::MAX, f32::MAX, f32::MAX);
-        let mut upper = Vec3::new(f32::MIN, f32::MIN, f32::MIN);
-
+        let mut aabb = Self::empty();
         for t in mesh {
-            let v = &t.vertices;
-
-            lower.x = lower.x.min(v[0].x.min(v[1].x).min(v[2].x));
-            lower.y = lower.y.min(v[0].y.min(v[1].y).min(v[2].y));
-            lower.z = lower.z.min(v[0].z.min(v[1].z).min(v[2].z));
+            aabb.extend(&t);
+        }
+        aabb
+    }
 
-            upper.x = upper.x.max(v[0].x.max(v[1].x).max(v[2].x));
-            upper.y = upper.y.max(v[0].y.max(v[1].y).max(v[2].y));
-            upper.z = upper.z.max(v[0].z.max(v[1].z).max(v[2].z));
+    /// The AABB containing nothing, merging it with another AABB yields the other one
+    pub fn empty() -> Self {
+        Self {
+            lower: Vec3::new(f32::MAX, f32::MAX, f32::MAX),
+            upper: Vec3::new(f32::MIN, f32::MIN, f32::MIN),
         }
+    }
+
+    /// Grows the AABB such that it contains the triangle
+    pub fn extend(&mut self, triangle: &Triangle) {
+        let v = &triangle.vertices;
 
-        Self { lower, upper }
+        self.lower.x = self.lower.x.min(v[0].x.min(v[1].x).min(v[2].x));
+        self.lower.y = self.lower.y.min(v[0].y.min(v[1].y).min(v[2].y));
+        self.lower.z = self.lower.z.min(v[0].z.min(v[1].z).min(v[2].z));
+
+        self.upper.x = self.upper.x.max(v[0].x.max(v[1].x).max(v[2].x));
+        self.upper.y = self.upper.y.max(v[0].y.max(v[1].y).max(v[2].y));
+        self.upper.z = self.upper.z.max(v[0].z.max(v[1].z).max(v[2].z));
+    }
+
+    /// The AABB containing both AABBs, e.g. the partial AABBs of the chunks of a mesh
+    pub fn merge(&self, other: &AABB) -> Self {
+        Self {
+            lower: self.lower.inf(&other.lower),
+            upper: self.upper.sup(&other.upper),
+        }
     }
 
     pub fn size(&self) -> Vec3 {
@@ -39,11 +57,12 @@ impl AABB {
     }
 
     pub fn apply_transform(&mut self, transform: &Mat4) {
-        self.lower = matmul(&transform, &self.lower);
-        self.upper = matmul(&transform, &self.upper);
+        self.lower = matmul(transform, &self.lower);
+        self.upper = matmul(transform, &self.upper);
     }
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
 
@@ -77,4 +96,35 @@ mod tests {
         let aabb = AABB::from_mesh(&mesh);
         assert_eq!(aabb.center(), Vec3::new(0.5, 0.5, 0.5));
     }
+
+    #[test]
+    fn test_merge() {
+        let mut lower = AABB::empty();
+        lower.extend(&Triangle::new(
+            [
+                Vec3::new(-1.0, 0.0, 0.0),
+                Vec3::new(0.0, -2.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+            ],
+            Vec3::new(0.0, 0.0, 0.0),
+        ));
+        let mut upper = AABB::empty();
+        upper.extend(&Triangle::new(
+            [
+                Vec3::new(3.0, 0.0, 0.0),
+                Vec3::new(0.0, 2.0, 0.0),
+                Vec3::new(0.0, 0.0, -1.0),
+            ],
+            Vec3::new(0.0, 0.0, 0.0),
+        ));
+
+        let aabb = lower.merge(&upper);
+        assert_eq!(aabb.lower, Vec3::new(-1.0, -2.0, -1.0));
+        assert_eq!(aabb.upper, Vec3::new(3.0, 2.0, 1.0));
+
+        // merging with an empty AABB changes nothing
+        let aabb = AABB::empty().merge(&aabb);
+        assert_eq!(aabb.lower, Vec3::new(-1.0, -2.0, -1.0));
+        assert_eq!(aabb.upper, Vec3::new(3.0, 2.0, 1.0));
+    }
 }
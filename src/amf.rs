@@ -0,0 +1,287 @@
+use crate::mesh::*;
+use crate::parser::ParseError;
+use anyhow::{anyhow, bail, Result};
+use roxmltree::{Document, Node};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Cursor, Read};
+use zip::ZipArchive;
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// Additive Manufacturing File Format (AMF) reader
+/// Handles plain and zip compressed files, every object becomes a part.
+/// Constellations are ignored, the objects are rendered at their original position.
+pub struct AmfParser;
+
+impl AmfParser {
+    pub fn from_buf<T: Read>(mut inner: T) -> Result<InMemorySource> {
+        let mut data = vec![];
+        inner.read_to_end(&mut data)?;
+
+        let content = if data.starts_with(ZIP_MAGIC) {
+            unzip(data)?
+        } else {
+            String::from_utf8(data).map_err(|_| anyhow!("AMF: file is not valid UTF-8"))?
+        };
+
+        let doc = Document::parse(content.trim_start_matches('\u{feff}'))?;
+        let root = doc.root_element();
+        if root.tag_name().name() != "amf" {
+            bail!("AMF: unexpected root element <{}>", root.tag_name().name());
+        }
+
+        // everything is converted to millimeters
+        let unit = match root.attribute("unit").unwrap_or("millimeter") {
+            "micron" => 0.001,
+            "millimeter" => 1.0,
+            "inch" => 25.4,
+            "feet" => 304.8,
+            "meter" => 1000.0,
+            unit => bail!("AMF: unknown unit '{}'", unit),
+        };
+
+        let mut materials = HashMap::new();
+        for material in children(root, "material") {
+            let id = text_attribute(material, "id")?;
+            materials.insert(id, child_color(material)?);
+        }
+
+        let mut triangles = vec![];
+        let mut parts = vec![];
+
+        for object in children(root, "object") {
+            let id = text_attribute(object, "id")?;
+            let object_color = child_color(object)?;
+            let part = parts.len();
+
+            for mesh in children(object, "mesh") {
+                let mut positions = vec![];
+                let mut vertex_colors = vec![];
+
+                for vertex in children(mesh, "vertices").flat_map(|n| children(n, "vertex")) {
+                    let coordinates = children(vertex, "coordinates")
+                        .next()
+                        .ok_or_else(|| anyhow!("AMF: <vertex> without <coordinates>"))?;
+                    positions.push(
+                        Vec3::new(
+                            parse_child(coordinates, "x")?,
+                            parse_child(coordinates, "y")?,
+                            parse_child(coordinates, "z")?,
+                        ) * unit,
+                    );
+                    vertex_colors.push(child_color(vertex)?);
+                }
+
+                for volume in children(mesh, "volume") {
+                    // the color of the volume overrides the color of its material
+                    let material_color = match volume.attribute("materialid") {
+                        Some(id) => *materials
+                            .get(id)
+                            .ok_or_else(|| anyhow!("AMF: material '{}' not found", id))?,
+                        None => None,
+                    };
+                    let volume_color = child_color(volume)?.or(material_color).or(object_color);
+
+                    for node in children(volume, "triangle") {
+                        let indices: [usize; 3] = [
+                            parse_child(node, "v1")?,
+                            parse_child(node, "v2")?,
+                            parse_child(node, "v3")?,
+                        ];
+                        if indices.iter().any(|i| *i >= positions.len()) {
+                            bail!("AMF: vertex index out of range in {:?}", indices);
+                        }
+
+                        let mut triangle = Triangle::new(
+                            [positions[indices[0]], positions[indices[1]], positions[indices[2]]],
+                            Vec3::new(0.0, 0.0, 0.0),
+                        );
+                        triangle.recalculate_normal();
+                        triangle.part = part as u32;
+
+                        // triangle colors take precedence over vertex colors
+                        match child_color(node)? {
+                            Some(color) => triangle.set_color(Some(color)),
+                            None => {
+                                triangle.set_color(volume_color);
+                                if let [Some(c0), Some(c1), Some(c2)] = [
+                                    vertex_colors[indices[0]],
+                                    vertex_colors[indices[1]],
+                                    vertex_colors[indices[2]],
+                                ] {
+                                    triangle.set_vertex_colors(Some([c0, c1, c2]));
+                                }
+                            }
+                        }
+
+                        if !triangle.has_finite_vertices() {
+                            return Err(ParseError::NonFinite {
+                                triangle: triangles.len() as u64,
+                            }
+                            .into());
+                        }
+                        triangles.push(triangle);
+                    }
+                }
+            }
+
+            parts.push(metadata(object, "name").unwrap_or_else(|| format!("object {}", id)));
+        }
+
+        if parts.is_empty() {
+            parts.push(String::new());
+        }
+
+        Ok(InMemorySource::new(triangles, parts))
+    }
+}
+
+impl AmfParser {
+    pub fn from_file(filename: &str) -> Result<InMemorySource> {
+        let file = fs::File::open(filename)?;
+        Self::from_buf(file)
+    }
+}
+
+// compressed files contain a single *.amf entry
+fn unzip(data: Vec<u8>) -> Result<String> {
+    let mut archive = ZipArchive::new(Cursor::new(data))?;
+
+    let name = archive
+        .file_names()
+        .find(|n| n.to_ascii_lowercase().ends_with(".amf"))
+        .map(|n| n.to_string())
+        .ok_or_else(|| anyhow!("AMF: archive doesn't contain an *.amf file"))?;
+
+    let mut content = String::new();
+    archive.by_name(&name)?.read_to_string(&mut content)?;
+
+    Ok(content)
+}
+
+// <color><r>1</r><g>0</g><b>0</b><a>1</a></color>
+fn child_color(node: Node) -> Result<Option<Vec3>> {
+    match children(node, "color").next() {
+        Some(color) => Ok(Some(Vec3::new(
+            parse_child(color, "r")?,
+            parse_child(color, "g")?,
+            parse_child(color, "b")?,
+        ))),
+        None => Ok(None),
+    }
+}
+
+fn metadata(node: Node, ty: &str) -> Option<String> {
+    children(node, "metadata")
+        .find(|n| n.attribute("type") == Some(ty))
+        .and_then(|n| n.text())
+        .map(|t| t.trim().to_string())
+}
+
+fn children<'a, 'input: 'a>(node: Node<'a, 'input>, name: &'a str) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(move |n| n.tag_name().name() == name)
+}
+
+fn text_attribute(node: Node, name: &str) -> Result<String> {
+    node.attribute(name)
+        .map(|v| v.to_string())
+        .ok_or_else(|| anyhow!("AMF: <{}> is missing the '{}' attribute", node.tag_name().name(), name))
+}
+
+fn parse_child<T: std::str::FromStr>(node: Node, name: &str) -> Result<T> {
+    let value = children(node, name)
+        .next()
+        .and_then(|n| n.text())
+        .ok_or_else(|| anyhow!("AMF: <{}> is missing <{}>", node.tag_name().name(), name))?
+        .trim();
+
+    value
+        .parse::<T>()
+        .map_err(|_| anyhow!("AMF: invalid value '{}' for <{}>", value, name))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::amf::AmfParser;
+    use crate::mesh::*;
+    use std::io::{Cursor, Write};
+    use zip::write::{FileOptions, ZipWriter};
+
+    const AMF: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<amf unit="inch" version="1.1">
+  <material id="2">
+    <color><r>1</r><g>0</g><b>0</b></color>
+  </material>
+  <object id="1">
+    <metadata type="name">wedge</metadata>
+    <mesh>
+      <vertices>
+        <vertex><coordinates><x>0</x><y>0</y><z>0</z></coordinates></vertex>
+        <vertex><coordinates><x>1</x><y>0</y><z>0</z></coordinates></vertex>
+        <vertex><coordinates><x>0</x><y>1</y><z>0</z></coordinates></vertex>
+        <vertex><coordinates><x>0</x><y>0</y><z>1</z></coordinates></vertex>
+      </vertices>
+      <volume materialid="2">
+        <triangle><v1>0</v1><v2>1</v2><v3>2</v3></triangle>
+        <triangle>
+          <color><r>0</r><g>1</g><b>0</b></color>
+          <v1>0</v1><v2>3</v2><v3>1</v3>
+        </triangle>
+      </volume>
+      <volume>
+        <color><r>0</r><g>0</g><b>1</b></color>
+        <triangle><v1>0</v1><v2>2</v2><v3>3</v3></triangle>
+      </volume>
+    </mesh>
+  </object>
+  <object id="5">
+    <mesh>
+      <vertices>
+        <vertex><coordinates><x>0</x><y>0</y><z>2</z></coordinates></vertex>
+        <vertex><coordinates><x>1</x><y>0</y><z>2</z></coordinates></vertex>
+        <vertex><coordinates><x>0</x><y>1</y><z>2</z></coordinates></vertex>
+      </vertices>
+      <volume><triangle><v1>0</v1><v2>1</v2><v3>2</v3></triangle></volume>
+    </mesh>
+  </object>
+</amf>"#;
+
+    #[test]
+    fn amf_volumes() {
+        let mut parser = AmfParser::from_buf(Cursor::new(AMF)).unwrap();
+        let mesh = parser.read_all().unwrap();
+
+        assert_eq!(parser.part_names(), &["wedge".to_string(), "object 5".to_string()]);
+        assert_eq!(mesh.len(), 4);
+
+        // inches are scaled to millimeters
+        assert_eq!(mesh[0].vertices[1], Vec3::new(25.4, 0.0, 0.0));
+        assert_eq!(mesh[0].normal, Vec3::new(0.0, 0.0, 1.0));
+
+        // material, triangle and volume colors
+        assert_eq!(mesh[0].color(), Some(Vec3::new(1.0, 0.0, 0.0)));
+        assert_eq!(mesh[1].color(), Some(Vec3::new(0.0, 1.0, 0.0)));
+        assert_eq!(mesh[2].color(), Some(Vec3::new(0.0, 0.0, 1.0)));
+
+        assert_eq!(mesh[3].part, 1);
+        assert_eq!(mesh[3].color(), None);
+    }
+
+    #[test]
+    fn amf_zipped() {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file("model.amf", FileOptions::default()).unwrap();
+        zip.write_all(AMF.as_bytes()).unwrap();
+        let data = zip.finish().unwrap().into_inner();
+
+        let mut parser = AmfParser::from_buf(Cursor::new(data)).unwrap();
+        assert_eq!(parser.read_all().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn amf_invalid_index() {
+        let amf = AMF.replace("<v3>3</v3>", "<v3>4</v3>");
+        assert!(AmfParser::from_buf(Cursor::new(amf)).is_err());
+    }
+}
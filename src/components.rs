@@ -0,0 +1,150 @@
+use crate::aabb::AABB;
+use crate::indexed::IndexedMesh;
+use crate::mesh::*;
+
+/// A set of triangles connected by shared vertices, e.g. one of several parts on a print plate
+#[derive(Debug, Clone)]
+pub struct Component {
+    /// indices of the triangles in the mesh
+    pub triangles: Vec<usize>,
+    pub aabb: AABB,
+}
+
+impl Component {
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+}
+
+impl IndexedMesh {
+    /// The connected components ordered by their first triangle
+    pub fn components(&self) -> Vec<Component> {
+        let mut sets = DisjointSets::new(self.vertices.len());
+        for face in &self.faces {
+            let [a, b, c] = face.indices;
+            sets.union(a, b);
+            sets.union(a, c);
+        }
+
+        // component of each set, numbered in the order of appearance
+        let mut ids = vec![None; self.vertices.len()];
+        let mut components: Vec<Component> = vec![];
+
+        for (i, face) in self.faces.iter().enumerate() {
+            let root = sets.find(face.indices[0]) as usize;
+            let id = *ids[root].get_or_insert_with(|| {
+                components.push(Component {
+                    triangles: vec![],
+                    aabb: AABB::empty(),
+                });
+                components.len() - 1
+            });
+
+            components[id].triangles.push(i);
+            components[id].aabb.extend(&self.triangle(face));
+        }
+
+        components
+    }
+}
+
+/// Finds the connected components of a triangle soup, see IndexedMesh::components
+pub fn components(mesh: &Mesh) -> Vec<Component> {
+    IndexedMesh::welded(mesh).components()
+}
+
+/// Assigns the index of its component to the part of each triangle such that the renderer draws
+/// each component in its own color (see RenderOptions::part_colors)
+pub fn assign_components(mesh: &mut Mesh) -> Vec<Component> {
+    let components = components(mesh);
+
+    for (id, component) in components.iter().enumerate() {
+        for i in &component.triangles {
+            mesh[*i].part = id as u32;
+        }
+    }
+
+    components
+}
+
+// union find with path halving
+pub(crate) struct DisjointSets {
+    parents: Vec<u32>,
+}
+
+impl DisjointSets {
+    pub(crate) fn new(len: usize) -> Self {
+        Self {
+            parents: (0..len as u32).collect(),
+        }
+    }
+
+    pub(crate) fn find(&mut self, mut i: u32) -> u32 {
+        while self.parents[i as usize] != i {
+            let grandparent = self.parents[self.parents[i as usize] as usize];
+            self.parents[i as usize] = grandparent;
+            i = grandparent;
+        }
+        i
+    }
+
+    pub(crate) fn union(&mut self, a: u32, b: u32) {
+        let (a, b) = (self.find(a), self.find(b));
+        // the lower index becomes the root
+        if a < b {
+            self.parents[b as usize] = a;
+        } else {
+            self.parents[a as usize] = b;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::components::{assign_components, components};
+    use crate::mesh::*;
+
+    fn triangle(offset: Vec3) -> Triangle {
+        Triangle::new(
+            [
+                offset,
+                offset + Vec3::new(1.0, 0.0, 0.0),
+                offset + Vec3::new(0.0, 1.0, 0.0),
+            ],
+            Vec3::new(0.0, 0.0, 1.0),
+        )
+    }
+
+    #[test]
+    fn separate_components() {
+        let mut mesh = Mesh::new(vec![
+            triangle(Vec3::new(0.0, 0.0, 0.0)),
+            triangle(Vec3::new(5.0, 0.0, 0.0)),
+            // touches the first triangle at a single vertex
+            triangle(Vec3::new(1.0, 0.0, 0.0)),
+            triangle(Vec3::new(5.0, 0.0, 2.0)),
+        ]);
+
+        let found = components(&mesh);
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[0].triangles, vec![0, 2]);
+        assert_eq!(found[1].triangle_count(), 1);
+        assert_eq!(found[0].aabb.lower, Vec3::new(0.0, 0.0, 0.0));
+        assert_eq!(found[0].aabb.upper, Vec3::new(2.0, 1.0, 0.0));
+        assert_eq!(found[2].aabb.lower, Vec3::new(5.0, 0.0, 2.0));
+
+        assign_components(&mut mesh);
+        let parts: Vec<u32> = mesh.into_iter().map(|t| t.part).collect();
+        assert_eq!(parts, vec![0, 1, 0, 2]);
+    }
+
+    #[test]
+    fn single_component() {
+        let mut second = triangle(Vec3::new(0.0, 0.0, 0.0));
+        second.vertices[2] = Vec3::new(1.0, 1.0, 0.0);
+        let mesh = Mesh::new(vec![triangle(Vec3::new(0.0, 0.0, 0.0)), second]);
+
+        assert_eq!(components(&mesh).len(), 1);
+        assert!(components(&Mesh::new(vec![])).is_empty());
+    }
+}
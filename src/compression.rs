@@ -0,0 +1,285 @@
+use crate::loader::ReadSeek;
+use anyhow::*;
+use flate2::read::MultiGzDecoder;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use zip::ZipArchive;
+
+const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+const ZSTD_MAGIC: &[u8] = b"\x28\xb5\x2f\xfd";
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+// e.g. a zip archive holding a gzipped model
+const MAX_NESTING: usize = 4;
+
+// guards against decompression bombs
+pub(crate) const MAX_DECOMPRESSED_SIZE: u64 = 2 << 30;
+
+// preferred over other files of zip archives, possibly compressed themselves
+const MODEL_EXTENSIONS: &[&str] = &["stl", "obj", "ply", "off", "gltf", "glb", "3mf", "amf"];
+const COMPRESSED_EXTENSIONS: &[&str] = &["gz", "zst", "zip"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Zip,
+}
+
+impl Compression {
+    pub fn sniff(header: &[u8]) -> Self {
+        if header.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if header.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else if header.starts_with(ZIP_MAGIC) {
+            Compression::Zip
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Decompresses gzip, zstd and zip compressed input into memory, anything else is passed through
+/// `entry` selects the model of a zip archive, by default the largest model file is picked.
+/// 3MF packages are zip archives themselves and are passed through as well.
+pub fn decompress(mut inner: Box<dyn ReadSeek>, mut entry: Option<&str>) -> Result<Box<dyn ReadSeek>> {
+    for _ in 0..MAX_NESTING {
+        let mut header = Vec::with_capacity(ZSTD_MAGIC.len());
+        (&mut inner).take(ZSTD_MAGIC.len() as u64).read_to_end(&mut header)?;
+        inner.seek(SeekFrom::Start(0))?;
+
+        let mut data = vec![];
+        match Compression::sniff(&header) {
+            Compression::None => return Ok(inner),
+            Compression::Gzip => {
+                read_limited(MultiGzDecoder::new(&mut inner), &mut data, MAX_DECOMPRESSED_SIZE)?;
+            }
+            Compression::Zstd => {
+                let decoder = ruzstd::StreamingDecoder::new(&mut inner).map_err(|e| anyhow!("zstd: {}", e))?;
+                read_limited(decoder, &mut data, MAX_DECOMPRESSED_SIZE)?;
+            }
+            Compression::Zip => {
+                let mut archive = ZipArchive::new(&mut inner)?;
+                if is_package(&archive) {
+                    drop(archive);
+                    inner.seek(SeekFrom::Start(0))?;
+                    return Ok(inner);
+                }
+
+                let name = match entry.take() {
+                    Some(name) => name.to_string(),
+                    None => largest_entry(&mut archive)?,
+                };
+                let file = archive
+                    .by_name(&name)
+                    .map_err(|_| anyhow!("zip: '{}' not found in the archive", name))?;
+                read_limited(file, &mut data, MAX_DECOMPRESSED_SIZE)?;
+            }
+        }
+
+        inner = Box::new(Cursor::new(data));
+    }
+
+    bail!("input is nested too deeply")
+}
+
+// the declared sizes of compressed data can't be trusted, hence the limit is checked while reading
+pub(crate) fn read_limited(inner: impl Read, data: &mut Vec<u8>, limit: u64) -> Result<()> {
+    inner.take(limit + 1).read_to_end(data)?;
+    if data.len() as u64 > limit {
+        bail!("decompressed input exceeds {} MiB", limit >> 20);
+    }
+    Ok(())
+}
+
+/// Names of the files in a zip archive which could hold a model
+pub fn zip_entries<T: Read + Seek>(inner: T) -> Result<Vec<String>> {
+    let mut archive = ZipArchive::new(inner)?;
+
+    let mut names = vec![];
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)?;
+        if is_model_candidate(file.name(), file.is_dir()) {
+            names.push(file.name().to_string());
+        }
+    }
+
+    Ok(names)
+}
+
+// 3MF packages describe their content with these files
+fn is_package<T: Read + Seek>(archive: &ZipArchive<T>) -> bool {
+    archive
+        .file_names()
+        .any(|n| n == "[Content_Types].xml" || n == "_rels/.rels")
+}
+
+// skips directories and the resource forks added by macOS
+fn is_model_candidate(name: &str, is_dir: bool) -> bool {
+    !is_dir && !name.starts_with("__MACOSX/") && !name.rsplit('/').next().unwrap_or_default().starts_with('.')
+}
+
+// compressed files and nested archives might hold a model as well
+fn has_model_extension(name: &str) -> bool {
+    let extension = name
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .rsplit('.')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    MODEL_EXTENSIONS.contains(&extension.as_str()) || COMPRESSED_EXTENSIONS.contains(&extension.as_str())
+}
+
+// the largest model, if there is none the largest file
+fn largest_entry<T: Read + Seek>(archive: &mut ZipArchive<T>) -> Result<String> {
+    let mut largest: Option<(bool, u64, String)> = None;
+
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)?;
+        let key = (has_model_extension(file.name()), file.size());
+        if is_model_candidate(file.name(), file.is_dir())
+            && largest.as_ref().map_or(true, |(model, size, _)| key > (*model, *size))
+        {
+            largest = Some((key.0, key.1, file.name().to_string()));
+        }
+    }
+
+    largest
+        .map(|(_, _, name)| name)
+        .ok_or_else(|| anyhow!("zip: the archive doesn't contain any files"))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::compression::{decompress, read_limited, zip_entries};
+    use flate2::write::GzEncoder;
+    use std::io::{Cursor, Read, Write};
+    use zip::write::{FileOptions, ZipWriter};
+
+    const TRI_BIN: &[u8] = include_bytes!("test_models/triangle.stl");
+
+    fn read(inner: Box<dyn crate::loader::ReadSeek>) -> Vec<u8> {
+        let mut data = vec![];
+        decompress(inner, None).unwrap().read_to_end(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn decompress_gzip() {
+        let mut encoder = GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(TRI_BIN).unwrap();
+        let gz = encoder.finish().unwrap();
+
+        assert_eq!(read(Box::new(Cursor::new(gz))), TRI_BIN);
+    }
+
+    #[test]
+    fn decompress_zstd() {
+        // single segment frame with a raw block
+        let content = b"OFF\n0 0 0\n";
+        let mut zst = b"\x28\xb5\x2f\xfd\x20".to_vec();
+        zst.push(content.len() as u8);
+        zst.extend_from_slice(&((1 | content.len() << 3) as u32).to_le_bytes()[..3]);
+        zst.extend_from_slice(content);
+
+        assert_eq!(read(Box::new(Cursor::new(zst))), content);
+    }
+
+    #[test]
+    fn decompress_zip() {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.add_directory("models/", FileOptions::default()).unwrap();
+        zip.start_file("models/small.stl", FileOptions::default()).unwrap();
+        zip.write_all(b"solid\nendsolid\n").unwrap();
+        zip.start_file("models/triangle.stl", FileOptions::default()).unwrap();
+        zip.write_all(TRI_BIN).unwrap();
+        let data = zip.finish().unwrap().into_inner();
+
+        assert_eq!(
+            zip_entries(Cursor::new(&data)).unwrap(),
+            &["models/small.stl", "models/triangle.stl"]
+        );
+
+        // the largest model by default
+        assert_eq!(read(Box::new(Cursor::new(data.clone()))), TRI_BIN);
+
+        let mut small = vec![];
+        decompress(Box::new(Cursor::new(data.clone())), Some("models/small.stl"))
+            .unwrap()
+            .read_to_end(&mut small)
+            .unwrap();
+        assert_eq!(small, b"solid\nendsolid\n");
+
+        assert!(decompress(Box::new(Cursor::new(data)), Some("missing.stl")).is_err());
+    }
+
+    #[test]
+    fn decompress_zip_prefers_models() {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file("README.txt", FileOptions::default()).unwrap();
+        zip.write_all(&[b'x'; 1000]).unwrap();
+        zip.start_file("images/preview.png", FileOptions::default()).unwrap();
+        zip.write_all(&[0; 2000]).unwrap();
+        zip.start_file("models/Triangle.STL", FileOptions::default()).unwrap();
+        zip.write_all(TRI_BIN).unwrap();
+        let data = zip.finish().unwrap().into_inner();
+
+        assert_eq!(read(Box::new(Cursor::new(data))), TRI_BIN);
+
+        // compressed models count as models
+        let mut encoder = GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(TRI_BIN).unwrap();
+        let gz = encoder.finish().unwrap();
+
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file("notes.txt", FileOptions::default()).unwrap();
+        zip.write_all(&[b'x'; 1000]).unwrap();
+        zip.start_file("triangle.stl.gz", FileOptions::default()).unwrap();
+        zip.write_all(&gz).unwrap();
+        let data = zip.finish().unwrap().into_inner();
+
+        assert_eq!(read(Box::new(Cursor::new(data))), TRI_BIN);
+
+        // so do nested archives
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file("triangle.stl", FileOptions::default()).unwrap();
+        zip.write_all(TRI_BIN).unwrap();
+        let inner = zip.finish().unwrap().into_inner();
+
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file("notes.txt", FileOptions::default()).unwrap();
+        zip.write_all(&[b'x'; 1000]).unwrap();
+        zip.start_file("model.zip", FileOptions::default()).unwrap();
+        zip.write_all(&inner).unwrap();
+        let data = zip.finish().unwrap().into_inner();
+
+        assert_eq!(read(Box::new(Cursor::new(data))), TRI_BIN);
+
+        // the largest file without any model
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file("small.dat", FileOptions::default()).unwrap();
+        zip.write_all(b"solid\nendsolid\n").unwrap();
+        zip.start_file("triangle.dat", FileOptions::default()).unwrap();
+        zip.write_all(TRI_BIN).unwrap();
+        let data = zip.finish().unwrap().into_inner();
+
+        assert_eq!(read(Box::new(Cursor::new(data))), TRI_BIN);
+    }
+
+    #[test]
+    fn decompress_size_limit() {
+        let mut data = vec![];
+        read_limited(&[0u8; 100][..], &mut data, 100).unwrap();
+        assert_eq!(data.len(), 100);
+
+        let mut data = vec![];
+        assert!(read_limited(&[0u8; 101][..], &mut data, 100).is_err());
+        // stops reading once over the limit
+        let mut data = vec![];
+        assert!(read_limited(std::io::repeat(0), &mut data, 100).is_err());
+        assert_eq!(data.len(), 101);
+    }
+}
@@ -0,0 +1,545 @@
+use crate::indexed::{Face, IndexedMesh};
+use crate::mesh::*;
+use glm::{DMat3, DVec3};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::ops::AddAssign;
+use std::sync::Arc;
+
+// borders are kept in place by planes perpendicular to their faces, weighted heavily
+const BORDER_WEIGHT: f64 = 100.0;
+
+// collapses turning a face by more than ~80° are rejected
+const MIN_NORMAL_COS: f64 = 0.2;
+
+/// Triangle count below which a model looks the same at the given resolution
+/// Most triangles of larger models are smaller than a pixel.
+pub fn target_for_resolution(width: u32, height: u32) -> usize {
+    width as usize * height as usize
+}
+
+impl IndexedMesh {
+    /// Simplifies the mesh to the target triangle count by collapsing the edges whose removal changes
+    /// the surface the least (quadric error metric by Garland and Heckbert)
+    /// Borders are preserved, degenerate faces are dropped. Collapses which would turn faces over or
+    /// pinch the surface together are skipped, hence the target might not be reached.
+    pub fn decimate(&self, target_triangles: usize) -> IndexedMesh {
+        if self.faces.len() <= target_triangles {
+            return self.clone();
+        }
+
+        Decimator::new(self).run(target_triangles)
+    }
+}
+
+/// Decimates a triangle soup, see IndexedMesh::decimate
+pub fn decimate(mesh: &Mesh, target_triangles: usize) -> Mesh {
+    if mesh.len() <= target_triangles {
+        return Mesh::new(mesh.into_iter().collect());
+    }
+
+    IndexedMesh::welded(mesh).decimate(target_triangles).to_mesh()
+}
+
+// symmetric 4x4 matrix summing up the squared distances to a set of planes (upper triangle, row by row)
+#[derive(Debug, Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    // plane through the point with the given unit normal
+    fn plane(normal: &DVec3, point: &DVec3, weight: f64) -> Self {
+        let (a, b, c) = (normal.x, normal.y, normal.z);
+        let d = -normal.dot(point);
+
+        Self([a * a, a * b, a * c, a * d, b * b, b * c, b * d, c * c, c * d, d * d].map(|q| q * weight))
+    }
+
+    fn error(&self, v: &DVec3) -> f64 {
+        let q = &self.0;
+        let (x, y, z) = (v.x, v.y, v.z);
+
+        q[0] * x * x
+            + 2.0 * q[1] * x * y
+            + 2.0 * q[2] * x * z
+            + 2.0 * q[3] * x
+            + q[4] * y * y
+            + 2.0 * q[5] * y * z
+            + 2.0 * q[6] * y
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9]
+    }
+
+    // the position with the least error, None if it isn't well defined (e.g. flat or straight areas)
+    fn minimum(&self) -> Option<DVec3> {
+        let q = &self.0;
+        let a = DMat3::new(q[0], q[1], q[2], q[1], q[4], q[5], q[2], q[5], q[7]);
+
+        let trace = q[0] + q[4] + q[7];
+        if a.determinant().abs() <= 1e-12 * trace * trace * trace {
+            return None;
+        }
+
+        a.try_inverse().map(|inverse| inverse * DVec3::new(-q[3], -q[6], -q[8]))
+    }
+}
+
+impl AddAssign for Quadric {
+    fn add_assign(&mut self, other: Self) {
+        for (q, o) in self.0.iter_mut().zip(&other.0) {
+            *q += o;
+        }
+    }
+}
+
+// an edge collapse, outdated once one of the vertices changed
+// kept small, most of them are outdated before they're popped off the queue
+struct Collapse {
+    cost: f32,
+    vertices: [u32; 2],
+    // sum of the stamps of the vertices, they only ever grow
+    stamps: u32,
+}
+
+impl Collapse {
+    fn is_current(&self, removed: &[bool], stamps: &[u32]) -> bool {
+        let [a, b] = self.vertices.map(|v| v as usize);
+        !removed[a] && !removed[b] && self.stamps == stamps[a] + stamps[b]
+    }
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    // the cheapest collapse comes first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+struct Decimator<'a> {
+    mesh: &'a IndexedMesh,
+    vertices: Vec<DVec3>,
+    quadrics: Vec<Quadric>,
+    // bumped whenever a vertex moves, invalidates its queued collapses
+    stamps: Vec<u32>,
+    removed: Vec<bool>,
+    faces: Vec<[u32; 3]>,
+    alive: Vec<bool>,
+    alive_count: usize,
+    // may contain dead faces
+    vertex_faces: Vec<Vec<usize>>,
+    queue: BinaryHeap<Collapse>,
+}
+
+impl<'a> Decimator<'a> {
+    fn new(mesh: &'a IndexedMesh) -> Self {
+        let vertices: Vec<DVec3> = mesh.vertices.iter().map(|v| glm::convert(*v)).collect();
+        let faces: Vec<[u32; 3]> = mesh.faces.iter().map(|f| f.indices).collect();
+        let alive: Vec<bool> = mesh.faces.iter().map(|f| !f.is_degenerate()).collect();
+
+        let mut quadrics = vec![Quadric::default(); vertices.len()];
+        for (face, _) in faces.iter().zip(&alive).filter(|(_, alive)| **alive) {
+            let [v0, v1, v2] = face.map(|i| vertices[i as usize]);
+            let cross = (v1 - v0).cross(&(v2 - v0));
+            let area = cross.norm() * 0.5;
+            if area > 0.0 {
+                let plane = Quadric::plane(&cross.normalize(), &v0, area);
+                for i in face {
+                    quadrics[*i as usize] += plane;
+                }
+            }
+        }
+
+        let edge_faces = mesh.edge_faces();
+        for ((a, b), adjacent) in &edge_faces {
+            // open and non-manifold edges
+            if adjacent.len() != 2 {
+                let [v0, v1, v2] = faces[adjacent[0]].map(|i| vertices[i as usize]);
+                let edge = vertices[*b as usize] - vertices[*a as usize];
+                let normal = edge.cross(&(v1 - v0).cross(&(v2 - v0))).normalize();
+                if normal.iter().all(|c| c.is_finite()) {
+                    let plane = Quadric::plane(&normal, &vertices[*a as usize], BORDER_WEIGHT * edge.norm_squared());
+                    quadrics[*a as usize] += plane;
+                    quadrics[*b as usize] += plane;
+                }
+            }
+        }
+
+        let mut vertex_faces = vec![vec![]; vertices.len()];
+        for (i, face) in faces.iter().enumerate().filter(|(i, _)| alive[*i]) {
+            for index in face {
+                vertex_faces[*index as usize].push(i);
+            }
+        }
+
+        let mut decimator = Self {
+            mesh,
+            stamps: vec![0; vertices.len()],
+            removed: vec![false; vertices.len()],
+            vertices,
+            quadrics,
+            alive_count: alive.iter().filter(|a| **a).count(),
+            faces,
+            alive,
+            vertex_faces,
+            queue: BinaryHeap::new(),
+        };
+
+        let collapses: Vec<Collapse> = edge_faces.keys().map(|(a, b)| decimator.collapse_of(*a, *b)).collect();
+        decimator.queue = BinaryHeap::from(collapses);
+
+        decimator
+    }
+
+    fn run(mut self, target_triangles: usize) -> IndexedMesh {
+        while self.alive_count > target_triangles {
+            let collapse = match self.queue.pop() {
+                Some(collapse) => collapse,
+                None => break,
+            };
+
+            if !collapse.is_current(&self.removed, &self.stamps) {
+                continue;
+            }
+
+            let [a, b] = collapse.vertices;
+            if !self.keeps_manifold(a, b) {
+                continue;
+            }
+
+            let (position, _) = self.optimum(a, b);
+            if self.turns_over(a, b, &position) || self.turns_over(b, a, &position) {
+                continue;
+            }
+
+            self.collapse(a, b, position);
+
+            // popping outdated collapses one by one is slow
+            if self.queue.len() > 2 * self.alive_count {
+                let (removed, stamps) = (&self.removed, &self.stamps);
+                self.queue.retain(|c| c.is_current(removed, stamps));
+            }
+        }
+
+        self.finish()
+    }
+
+    fn collapse_of(&self, a: u32, b: u32) -> Collapse {
+        Collapse {
+            cost: self.optimum(a, b).1 as f32,
+            vertices: [a, b],
+            stamps: self.stamps[a as usize] + self.stamps[b as usize],
+        }
+    }
+
+    // position of the merged vertices and its error
+    fn optimum(&self, a: u32, b: u32) -> (DVec3, f64) {
+        let mut quadric = self.quadrics[a as usize];
+        quadric += self.quadrics[b as usize];
+
+        let (va, vb) = (self.vertices[a as usize], self.vertices[b as usize]);
+        let midpoint = (va + vb) * 0.5;
+
+        // the optimum of nearly flat areas can be far off, the endpoints and the midpoint are safe choices
+        let position = match quadric.minimum() {
+            Some(p) if glm::distance(&p, &midpoint) <= glm::distance(&va, &vb) => p,
+            _ => *[va, vb, midpoint]
+                .iter()
+                .min_by(|p, q| quadric.error(p).total_cmp(&quadric.error(q)))
+                .unwrap_or(&midpoint),
+        };
+
+        (position, quadric.error(&position))
+    }
+
+    // the other vertices of the alive faces around the vertex, and whether one of its edges is a border
+    fn neighbourhood(&self, vertex: u32) -> (Vec<u32>, bool) {
+        let mut edges: Vec<u32> = self.vertex_faces[vertex as usize]
+            .iter()
+            .filter(|f| self.alive[**f])
+            .flat_map(|f| self.faces[*f])
+            .filter(|i| *i != vertex)
+            .collect();
+        edges.sort_unstable();
+
+        // every edge of a closed surface is shared by two faces
+        let border =
+            (0..edges.len()).any(|i| (i == 0 || edges[i - 1] != edges[i]) && edges.get(i + 1) != Some(&edges[i]));
+        edges.dedup();
+
+        (edges, border)
+    }
+
+    // link condition (Dey et al.): the vertices adjacent to both ends of the edge have to be the tips of the
+    // faces of the edge, otherwise the collapse pinches the surface together
+    fn keeps_manifold(&self, a: u32, b: u32) -> bool {
+        let (neighbours_a, border_a) = self.neighbourhood(a);
+        let (neighbours_b, border_b) = self.neighbourhood(b);
+
+        let tips: Vec<u32> = self.vertex_faces[a as usize]
+            .iter()
+            .filter(|f| self.alive[**f] && self.faces[**f].contains(&b))
+            .flat_map(|f| self.faces[*f])
+            .filter(|i| *i != a && *i != b)
+            .collect();
+
+        // joining two borders through the inside
+        if border_a && border_b && tips.len() != 1 {
+            return false;
+        }
+
+        neighbours_a
+            .iter()
+            .filter(|v| neighbours_b.binary_search(v).is_ok())
+            .all(|v| tips.contains(v))
+    }
+
+    // whether moving the vertex turns one of its faces not shared with the other vertex over
+    fn turns_over(&self, vertex: u32, other: u32, position: &DVec3) -> bool {
+        self.vertex_faces[vertex as usize]
+            .iter()
+            .filter(|f| self.alive[**f] && !self.faces[**f].contains(&other))
+            .any(|f| {
+                let face = self.faces[*f];
+                let before = face.map(|i| self.vertices[i as usize]);
+                let after = face.map(|i| {
+                    if i == vertex {
+                        *position
+                    } else {
+                        self.vertices[i as usize]
+                    }
+                });
+
+                let normal_before = (before[1] - before[0]).cross(&(before[2] - before[0]));
+                let normal_after = (after[1] - after[0]).cross(&(after[2] - after[0]));
+
+                normal_after.dot(&normal_before) <= MIN_NORMAL_COS * normal_after.norm() * normal_before.norm()
+            })
+    }
+
+    // merges b into a
+    fn collapse(&mut self, a: u32, b: u32, position: DVec3) {
+        self.vertices[a as usize] = position;
+        let quadric = self.quadrics[b as usize];
+        self.quadrics[a as usize] += quadric;
+        self.stamps[a as usize] += 1;
+        self.removed[b as usize] = true;
+
+        for f in std::mem::take(&mut self.vertex_faces[b as usize]) {
+            if !self.alive[f] {
+                continue;
+            }
+
+            if self.faces[f].contains(&a) {
+                // the faces of the collapsed edge vanish
+                self.alive[f] = false;
+                self.alive_count -= 1;
+            } else {
+                for index in self.faces[f].iter_mut().filter(|i| **i == b) {
+                    *index = a;
+                }
+                self.vertex_faces[a as usize].push(f);
+            }
+        }
+
+        let alive = &self.alive;
+        self.vertex_faces[a as usize].retain(|f| alive[*f]);
+
+        let mut neighbours: Vec<u32> = self.vertex_faces[a as usize]
+            .iter()
+            .flat_map(|f| self.faces[*f])
+            .filter(|i| *i != a)
+            .collect();
+        neighbours.sort_unstable();
+        neighbours.dedup();
+
+        for neighbour in neighbours {
+            let collapse = self.collapse_of(a, neighbour);
+            self.queue.push(collapse);
+        }
+    }
+
+    // the remaining faces with their original attributes
+    fn finish(self) -> IndexedMesh {
+        let mut remap = vec![None; self.vertices.len()];
+        let mut vertices: Vec<Vec3> = vec![];
+        let mut faces = vec![];
+
+        for (i, face) in self.faces.iter().enumerate().filter(|(i, _)| self.alive[*i]) {
+            let indices = face.map(|v| {
+                *remap[v as usize].get_or_insert_with(|| {
+                    vertices.push(glm::convert(self.vertices[v as usize]));
+                    vertices.len() as u32 - 1
+                })
+            });
+
+            let original = &self.mesh.faces[i];
+            let [v0, v1, v2] = indices.map(|i| vertices[i as usize]);
+            // faces collapsed to zero area have no direction of their own
+            let cross = (v1 - v0).cross(&(v2 - v0));
+            let mut normal = if cross.norm() > 0.0 && cross.iter().all(|c| c.is_finite()) {
+                cross.normalize()
+            } else {
+                original.normal
+            };
+            if glm::dot(&normal, &original.normal) < 0.0 {
+                normal = -normal;
+            }
+
+            // the vertices moved, the smooth normals are outdated
+            let attributes = match &original.attributes {
+                Some(a) if a.normals.is_some() => {
+                    let kept = Attributes { normals: None, ..**a };
+                    (kept != Attributes::default()).then(|| Arc::new(kept))
+                }
+                a => a.clone(),
+            };
+
+            faces.push(Face {
+                indices,
+                normal,
+                part: original.part,
+                attributes,
+            });
+        }
+
+        IndexedMesh { vertices, faces }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::aabb::AABB;
+    use crate::decimate::decimate;
+    use crate::mesh::*;
+    use crate::stats::Stats;
+    use std::f32::consts::PI;
+
+    // latitude-longitude sphere around the origin, wound counterclockwise seen from outside
+    fn sphere(radius: f32, rings: usize, segments: usize) -> Mesh {
+        let point = |ring: usize, segment: usize| {
+            let (theta, phi) = (
+                ring as f32 / rings as f32 * PI,
+                segment as f32 / segments as f32 * 2.0 * PI,
+            );
+            Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()) * radius
+        };
+
+        let mut triangles = vec![];
+        let mut push = |vertices: [Vec3; 3]| {
+            let mut triangle = Triangle::new(vertices, Vec3::new(0.0, 0.0, 0.0));
+            triangle.recalculate_normal();
+            triangles.push(triangle);
+        };
+
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let [a, b, c, d] = [
+                    point(ring, segment),
+                    point(ring + 1, segment),
+                    point(ring + 1, segment + 1),
+                    point(ring, segment + 1),
+                ];
+                // the poles are fans
+                if ring != 0 {
+                    push([a, c, d]);
+                }
+                if ring != rings - 1 {
+                    push([a, b, c]);
+                }
+            }
+        }
+
+        Mesh::new(triangles)
+    }
+
+    // flat square made of a grid of quads
+    fn grid(size: f32, quads: usize) -> Mesh {
+        let step = size / quads as f32;
+        let point = |x: usize, y: usize| Vec3::new(x as f32 * step, y as f32 * step, 0.0);
+
+        let mut triangles = vec![];
+        for x in 0..quads {
+            for y in 0..quads {
+                let normal = Vec3::new(0.0, 0.0, 1.0);
+                triangles.push(Triangle::new(
+                    [point(x, y), point(x + 1, y), point(x + 1, y + 1)],
+                    normal,
+                ));
+                triangles.push(Triangle::new(
+                    [point(x, y), point(x + 1, y + 1), point(x, y + 1)],
+                    normal,
+                ));
+            }
+        }
+
+        Mesh::new(triangles)
+    }
+
+    #[test]
+    fn decimate_sphere() {
+        let mesh = sphere(10.0, 32, 64);
+        let decimated = decimate(&mesh, 500);
+
+        assert!(decimated.len() <= 500 && decimated.len() > 400);
+
+        // the surface stays closed and round
+        let stats = Stats::from_mesh(&decimated);
+        assert!(stats.is_watertight());
+        assert!(stats.volume > 0.9 * Stats::from_mesh(&mesh).volume);
+        for t in &decimated {
+            for v in &t.vertices {
+                assert!((v.norm() - 10.0).abs() < 0.5);
+            }
+            // normals still point outwards
+            assert!(glm::dot(&t.normal, &t.vertices[0]) > 0.0);
+        }
+    }
+
+    #[test]
+    fn decimate_stays_manifold() {
+        // far below what the shape can keep
+        let mesh = sphere(10.0, 16, 32);
+        for target in [4, 8, 16, 32] {
+            let decimated = decimate(&mesh, target);
+
+            let stats = Stats::from_mesh(&decimated);
+            assert!(stats.is_watertight(), "{}", target);
+            assert!(decimated.into_iter().all(|t| t.normal.iter().all(|c| c.is_finite())));
+        }
+    }
+
+    #[test]
+    fn decimate_keeps_borders() {
+        let mesh = grid(10.0, 16);
+        let decimated = decimate(&mesh, 20);
+
+        assert!(decimated.len() <= 20);
+        assert_eq!(AABB::from_iterable(&decimated).size(), Vec3::new(10.0, 10.0, 0.0));
+
+        // the square is still covered entirely
+        let stats = Stats::from_mesh(&decimated);
+        assert!((stats.area - 100.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn decimate_small_mesh() {
+        let mesh = grid(1.0, 2);
+        let decimated = decimate(&mesh, 100);
+        assert_eq!(decimated.len(), mesh.len());
+        assert_eq!(decimated[3], mesh[3]);
+    }
+}
@@ -0,0 +1,178 @@
+use crate::indexed::{Edge, Face, IndexedMesh};
+use crate::mesh::*;
+use std::collections::VecDeque;
+
+/// Defects of a mesh which commonly make prints fail
+pub struct Defects {
+    /// edges with a single adjacent face (holes)
+    pub open_edges: Vec<[Vec3; 2]>,
+    /// edges shared by more than two faces
+    pub non_manifold_edges: Vec<[Vec3; 2]>,
+    /// faces whose winding disagrees with their neighbours
+    pub flipped_faces: Vec<usize>,
+}
+
+impl Defects {
+    pub fn from_mesh(mesh: &Mesh) -> Self {
+        Self::find(&IndexedMesh::welded(mesh))
+    }
+
+    pub fn find(mesh: &IndexedMesh) -> Self {
+        let edge_faces = mesh.edge_faces();
+        let line = |(a, b): &Edge| [mesh.vertices[*a as usize], mesh.vertices[*b as usize]];
+
+        let mut open_edges = vec![];
+        let mut non_manifold_edges = vec![];
+        // a face has a neighbour across each of its edges at most
+        let mut neighbours = vec![[None; 3]; mesh.faces.len()];
+        let mut link = |f: usize, g: usize, agree: bool| {
+            if let Some(slot) = neighbours[f].iter_mut().find(|n| n.is_none()) {
+                *slot = Some((g, agree));
+            }
+        };
+
+        for (edge, faces) in &edge_faces {
+            match faces.as_slice() {
+                [_] => open_edges.push(line(edge)),
+                [f, g] => {
+                    // neighbours sharing an edge agree if they traverse it in opposite directions
+                    let agree = direction(&mesh.faces[*f], edge) != direction(&mesh.faces[*g], edge);
+                    link(*f, *g, agree);
+                    link(*g, *f, agree);
+                }
+                _ => non_manifold_edges.push(line(edge)),
+            }
+        }
+
+        Self {
+            open_edges,
+            non_manifold_edges,
+            flipped_faces: flipped_faces(&neighbours),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.open_edges.is_empty() && self.non_manifold_edges.is_empty() && self.flipped_faces.is_empty()
+    }
+
+    /// Colors the flipped faces of the mesh the defects have been found in
+    /// The faces are turned around as well, otherwise they would be culled.
+    pub fn tint(&self, mesh: &mut Mesh, color: Vec3) {
+        for i in &self.flipped_faces {
+            let triangle = &mut mesh[*i];
+            triangle.flip();
+            triangle.set_color(Some(color));
+            triangle.set_vertex_colors(None);
+        }
+    }
+}
+
+// whether the face traverses the edge from its lower to its higher vertex
+fn direction(face: &Face, (a, b): &Edge) -> bool {
+    (0..3).any(|i| face.indices[i] == *a && face.indices[(i + 1) % 3] == *b)
+}
+
+// the orientation of the first face is propagated through each connected patch,
+// the smaller group of faces disagreeing with each other is the flipped one
+fn flipped_faces(neighbours: &[[Option<(usize, bool)>; 3]]) -> Vec<usize> {
+    let mut orientation: Vec<Option<bool>> = vec![None; neighbours.len()];
+    let mut flipped = vec![];
+
+    for seed in 0..neighbours.len() {
+        if orientation[seed].is_some() {
+            continue;
+        }
+
+        let mut patch = vec![seed];
+        let mut queue = VecDeque::from(vec![seed]);
+        orientation[seed] = Some(false);
+
+        while let Some(f) = queue.pop_front() {
+            let flip = orientation[f].unwrap_or_default();
+            for (g, agree) in neighbours[f].iter().flatten() {
+                if orientation[*g].is_none() {
+                    orientation[*g] = Some(if *agree { flip } else { !flip });
+                    patch.push(*g);
+                    queue.push_back(*g);
+                }
+            }
+        }
+
+        let (a, b): (Vec<usize>, Vec<usize>) = patch.into_iter().partition(|f| orientation[*f] == Some(true));
+        flipped.extend(if a.len() <= b.len() { a } else { b });
+    }
+
+    flipped.sort_unstable();
+    flipped
+}
+
+#[cfg(test)]
+mod test {
+    use crate::defects::Defects;
+    use crate::mesh::*;
+
+    // four faces of a tetrahedron, wound counterclockwise seen from outside
+    fn tetrahedron() -> Mesh {
+        let v = [
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ];
+        let faces = [[0, 2, 1], [0, 1, 3], [1, 2, 3], [0, 3, 2]];
+
+        Mesh::new(
+            faces
+                .iter()
+                .map(|[a, b, c]| {
+                    let mut triangle = Triangle::new([v[*a], v[*b], v[*c]], Vec3::new(0.0, 0.0, 0.0));
+                    triangle.recalculate_normal();
+                    triangle
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn no_defects() {
+        assert!(Defects::from_mesh(&tetrahedron()).is_empty());
+    }
+
+    #[test]
+    fn flipped_face() {
+        let mut mesh = tetrahedron();
+        mesh[2].vertices.swap(0, 1);
+
+        let defects = Defects::from_mesh(&mesh);
+        assert_eq!(defects.flipped_faces, vec![2]);
+        assert!(defects.open_edges.is_empty());
+
+        let normal = mesh[2].normal;
+        defects.tint(&mut mesh, Vec3::new(1.0, 0.0, 1.0));
+        assert_eq!(mesh[2].color(), Some(Vec3::new(1.0, 0.0, 1.0)));
+        assert_eq!(mesh[2].normal, -normal);
+        assert_eq!(mesh[1].color(), None);
+
+        // the tinted face agrees with its neighbours again
+        assert!(Defects::from_mesh(&mesh).is_empty());
+    }
+
+    #[test]
+    fn open_and_non_manifold_edges() {
+        let mut mesh = tetrahedron();
+        let fin = Triangle::new(
+            [
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.5, -1.0, 0.0),
+            ],
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        mesh.push(fin);
+
+        let defects = Defects::from_mesh(&mesh);
+        assert_eq!(defects.non_manifold_edges.len(), 1);
+        assert_eq!(defects.open_edges.len(), 2);
+        assert!(defects.non_manifold_edges[0].contains(&Vec3::new(1.0, 0.0, 0.0)));
+    }
+}
@@ -2,9 +2,14 @@ use std::ffi::CStr;
 use std::mem::forget;
 use std::os::raw::c_char;
 
-use crate::parser::Parser;
+use crate::aabb::AABB;
+use crate::loader::{open, LoadOptions};
+use crate::mesh::Mesh;
+use crate::orientation::orient;
+use crate::pose::detect_up_axis;
 use crate::rasterbackend::RasterBackend;
-use std::time::Duration;
+use crate::transform::{Axis, Transform};
+use anyhow::Result;
 
 #[repr(C)]
 pub struct PictureBuffer {
@@ -30,43 +35,95 @@ pub struct RenderSettings {
     timeout: u64,
 }
 
+/// Axis of the model pointing upwards
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UpAxis {
+    Z,
+    Y,
+    X,
+    /// guessed from the format and the geometry
+    Auto,
+}
+
+/// Options in addition to the RenderSettings, new ones are appended
+/// Set size to sizeof(RenderOptions), the options past the size the caller was built with keep their defaults.
+#[repr(C)]
+pub struct RenderOptions {
+    /// size of the struct in bytes
+    size: u32,
+    /// turn flipped faces and inside out models around
+    repair: bool,
+    /// rotated onto the z axis
+    up_axis: UpAxis,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            size: std::mem::size_of::<Self>() as u32,
+            repair: false,
+            up_axis: UpAxis::Z,
+        }
+    }
+}
+
+impl RenderOptions {
+    // copies the options known to the caller, null for the defaults
+    unsafe fn read(options: *const RenderOptions) -> Self {
+        let mut read = Self::default();
+        if !options.is_null() {
+            let len = ((*options).size as usize).min(std::mem::size_of::<Self>());
+            std::ptr::copy_nonoverlapping(options as *const u8, &mut read as *mut Self as *mut u8, len);
+        }
+        read
+    }
+}
+
 #[no_mangle]
 /// Renders a mesh to a picture
 /// Free the buffer with free_picture_buffer
 pub extern "C" fn render(path: *const c_char, settings: RenderSettings) -> PictureBuffer {
+    render_with_options(path, settings, std::ptr::null())
+}
+
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+/// Renders a mesh to a picture, options may be null
+/// Free the buffer with free_picture_buffer
+pub extern "C" fn render_with_options(
+    path: *const c_char,
+    settings: RenderSettings,
+    options: *const RenderOptions,
+) -> PictureBuffer {
     let path = unsafe { CStr::from_ptr(path).to_str().unwrap() };
+    let options = unsafe { RenderOptions::read(options) };
 
     let mut backend = RasterBackend::new(settings.width, settings.height);
-    let parser = Parser::from_file(path, true);
-
-    if let Ok(mut parser) = parser {
-        let mesh = parser.read_all();
+    if let Ok((mesh, aabb)) = load(path, &options) {
+        let scale = backend.fit_aabb_scale(&aabb);
 
-        if let Ok(mesh) = mesh {
-            let (aabb, scale) = backend.fit_mesh_scale(&mesh);
+        // set flags
+        backend.render_options.draw_size_hint = settings.size_hint;
 
-            // set flags
-            backend.render_options.draw_size_hint = settings.size_hint;
+        // render
+        let mut pic = backend.render(&mesh, scale, &aabb, None);
 
-            // render
-            let mut pic = backend.render(&mesh, scale, &aabb, None);
+        let boxed_data = pic.data_as_boxed_slice();
+        let data = boxed_data.as_ptr();
+        let len = pic.data().len() as u32;
+        let stride = pic.stride();
+        let depth = pic.depth();
 
-            let boxed_data = pic.data_as_boxed_slice();
-            let data = boxed_data.as_ptr();
-            let len = pic.data().len() as u32;
-            let stride = pic.stride() as u32;
-            let depth = pic.depth() as u32;
+        // leak the memory owned by boxed_data
+        forget(boxed_data);
 
-            // leak the memory owned by boxed_data
-            forget(boxed_data);
-
-            return PictureBuffer {
-                data,
-                len,
-                stride,
-                depth,
-            };
-        }
+        return PictureBuffer {
+            data,
+            len,
+            stride,
+            depth,
+        };
     }
 
     PictureBuffer {
@@ -77,6 +134,34 @@ pub extern "C" fn render(path: *const c_char, settings: RenderSettings) -> Pictu
     }
 }
 
+// repairs the orientation if requested and rotates the up axis onto the z axis like the CLI does
+fn load(path: &str, options: &RenderOptions) -> Result<(Mesh, AABB)> {
+    let load_options = LoadOptions {
+        recalculate_normals: true,
+        ..LoadOptions::default()
+    };
+    let (mut source, format) = open(path, &load_options)?;
+    let (mut mesh, mut aabb) = source.read_all_with_bounds()?;
+
+    if options.repair {
+        orient(&mut mesh);
+    }
+
+    let up_axis = match options.up_axis {
+        UpAxis::X => Axis::X,
+        UpAxis::Y => Axis::Y,
+        UpAxis::Z => Axis::Z,
+        UpAxis::Auto => detect_up_axis(&mesh, Some(format)),
+    };
+    let transform = Transform::up_axis(up_axis);
+    if !transform.is_identity() {
+        transform.apply_mesh(&mut mesh);
+        aabb = AABB::from_mesh(&mesh);
+    }
+
+    Ok((mesh, aabb))
+}
+
 #[no_mangle]
 /// Frees the memory of a PictureBuffer
 pub extern "C" fn free_picture_buffer(buffer: PictureBuffer) {
@@ -84,6 +169,27 @@ pub extern "C" fn free_picture_buffer(buffer: PictureBuffer) {
         let s = std::slice::from_raw_parts_mut(buffer.data as *mut u8, buffer.len as usize);
 
         // put the memory back into the box such that is can be freed
-        Box::from_raw(s as *mut [u8]);
+        drop(Box::from_raw(s as *mut [u8]));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ffi::{RenderOptions, UpAxis};
+
+    #[test]
+    fn options_of_older_callers() {
+        // a caller built before the up axis was added
+        let options = RenderOptions {
+            size: 8,
+            repair: true,
+            up_axis: UpAxis::Auto,
+        };
+        let read = unsafe { RenderOptions::read(&options) };
+        assert!(read.repair);
+        assert_eq!(read.up_axis, UpAxis::Z);
+
+        let read = unsafe { RenderOptions::read(std::ptr::null()) };
+        assert!(!read.repair);
     }
 }
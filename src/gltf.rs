@@ -0,0 +1,627 @@
+use crate::mesh::*;
+use crate::parser::ParseError;
+use anyhow::{anyhow, bail, Result};
+use byteorder::{ByteOrder, LittleEndian};
+use serde_json::Value;
+use std::fs;
+use std::io::Read;
+
+const GLB_MAGIC: &[u8] = b"glTF";
+const GLB_CHUNK_JSON: u32 = 0x4E4F_534A;
+const GLB_CHUNK_BIN: u32 = 0x004E_4942;
+const MAX_NODE_DEPTH: usize = 64;
+
+// glTF is specified in meters
+const METERS_TO_MM: f32 = 1000.0;
+
+// primitive modes
+const TRIANGLES: u64 = 4;
+const TRIANGLE_STRIP: u64 = 5;
+const TRIANGLE_FAN: u64 = 6;
+
+/// glTF 2.0 and GLB reader
+/// Only embedded buffers (GLB binary chunk or data URIs) are supported,
+/// every node referencing a mesh becomes a part.
+pub struct GltfParser;
+
+impl GltfParser {
+    pub fn from_buf<T: Read>(mut inner: T) -> Result<InMemorySource> {
+        let mut data = vec![];
+        inner.read_to_end(&mut data)?;
+
+        let (json, bin) = if data.starts_with(GLB_MAGIC) {
+            split_glb(&data)?
+        } else {
+            (data.as_slice(), None)
+        };
+
+        let doc: Value = serde_json::from_slice(json)?;
+        let buffers = load_buffers(&doc, bin)?;
+
+        let mut reader = Reader {
+            doc: &doc,
+            buffers: &buffers,
+            triangles: vec![],
+            parts: vec![],
+            visited: vec![false; array(&doc, "nodes").len()],
+        };
+
+        // nodes of the default scene or all root nodes if there is no scene
+        let roots = match scene_nodes(&doc) {
+            Some(nodes) => nodes,
+            None => root_nodes(&doc),
+        };
+
+        let transform = Mat4::new_scaling(METERS_TO_MM);
+        for node in roots {
+            reader.node(node, &transform, 0)?;
+        }
+
+        let triangles = reader.triangles;
+        let mut parts = reader.parts;
+        if parts.is_empty() {
+            parts.push(String::new());
+        }
+
+        Ok(InMemorySource::new(triangles, parts))
+    }
+}
+
+impl GltfParser {
+    pub fn from_file(filename: &str) -> Result<InMemorySource> {
+        let file = fs::File::open(filename)?;
+        Self::from_buf(file)
+    }
+}
+
+// returns the json and the optional binary chunk
+fn split_glb(data: &[u8]) -> Result<(&[u8], Option<&[u8]>)> {
+    if data.len() < 12 {
+        bail!("glTF: truncated GLB header");
+    }
+
+    let version = LittleEndian::read_u32(&data[4..8]);
+    if version != 2 {
+        bail!("glTF: unsupported GLB version {}", version);
+    }
+
+    let mut json = None;
+    let mut bin = None;
+    let mut offset = 12;
+
+    while offset + 8 <= data.len() {
+        let length = LittleEndian::read_u32(&data[offset..offset + 4]) as usize;
+        let kind = LittleEndian::read_u32(&data[offset + 4..offset + 8]);
+        let chunk = data
+            .get(offset + 8..offset + 8 + length)
+            .ok_or_else(|| anyhow!("glTF: truncated GLB chunk"))?;
+
+        match kind {
+            GLB_CHUNK_JSON if json.is_none() => json = Some(chunk),
+            GLB_CHUNK_BIN if bin.is_none() => bin = Some(chunk),
+            _ => {}
+        }
+
+        // chunks are 4 byte aligned
+        offset += 8 + length.div_ceil(4) * 4;
+    }
+
+    Ok((json.ok_or_else(|| anyhow!("glTF: missing JSON chunk"))?, bin))
+}
+
+fn load_buffers(doc: &Value, bin: Option<&[u8]>) -> Result<Vec<Vec<u8>>> {
+    let mut buffers = vec![];
+
+    for (i, buffer) in array(doc, "buffers").iter().enumerate() {
+        let data = match buffer["uri"].as_str() {
+            Some(uri) if uri.starts_with("data:") => {
+                let (_, payload) = uri
+                    .split_once(";base64,")
+                    .ok_or_else(|| anyhow!("glTF: buffer {} is not base64 encoded", i))?;
+                base64::decode(payload)?
+            }
+            Some(uri) => bail!("glTF: external buffer '{}' is not supported", uri),
+            // the first buffer of a GLB file refers to the binary chunk
+            None if i == 0 => bin.ok_or_else(|| anyhow!("glTF: missing binary chunk"))?.to_vec(),
+            None => bail!("glTF: buffer {} has no data", i),
+        };
+        buffers.push(data);
+    }
+
+    Ok(buffers)
+}
+
+fn array<'a>(value: &'a Value, key: &str) -> &'a [Value] {
+    value[key].as_array().map_or(&[], |a| a.as_slice())
+}
+
+fn index(value: &Value, key: &str) -> Option<usize> {
+    value[key].as_u64().map(|v| v as usize)
+}
+
+fn floats(value: &Value) -> Option<Vec<f32>> {
+    value.as_array()?.iter().map(|v| v.as_f64().map(|v| v as f32)).collect()
+}
+
+fn scene_nodes(doc: &Value) -> Option<Vec<usize>> {
+    let scene = array(doc, "scenes").get(index(doc, "scene").unwrap_or(0))?;
+    Some(
+        array(scene, "nodes")
+            .iter()
+            .filter_map(|n| n.as_u64())
+            .map(|n| n as usize)
+            .collect(),
+    )
+}
+
+fn root_nodes(doc: &Value) -> Vec<usize> {
+    let nodes = array(doc, "nodes");
+    let mut is_child = vec![false; nodes.len()];
+    for child in nodes
+        .iter()
+        .flat_map(|n| array(n, "children"))
+        .filter_map(|c| c.as_u64())
+    {
+        if let Some(is_child) = is_child.get_mut(child as usize) {
+            *is_child = true;
+        }
+    }
+
+    (0..nodes.len()).filter(|n| !is_child[*n]).collect()
+}
+
+// local transform of a node, either a matrix or translation, rotation and scale
+fn node_transform(node: &Value) -> Mat4 {
+    if let Some(m) = floats(&node["matrix"]).filter(|m| m.len() == 16) {
+        return glm::make_mat4(&m);
+    }
+
+    let mut transform = Mat4::identity();
+    if let Some(t) = floats(&node["translation"]).filter(|t| t.len() == 3) {
+        transform *= Mat4::new_translation(&Vec3::new(t[0], t[1], t[2]));
+    }
+    if let Some(r) = floats(&node["rotation"]).filter(|r| r.len() == 4) {
+        transform *= glm::quat_to_mat4(&glm::quat_normalize(&glm::quat(r[0], r[1], r[2], r[3])));
+    }
+    if let Some(s) = floats(&node["scale"]).filter(|s| s.len() == 3) {
+        transform *= Mat4::new_nonuniform_scaling(&Vec3::new(s[0], s[1], s[2]));
+    }
+
+    transform
+}
+
+struct Reader<'a> {
+    doc: &'a Value,
+    buffers: &'a [Vec<u8>],
+    triangles: Vec<Triangle>,
+    parts: Vec<String>,
+    // the nodes form a strict tree, shared children would be instantiated over and over again
+    visited: Vec<bool>,
+}
+
+impl<'a> Reader<'a> {
+    fn node(&mut self, index: usize, parent: &Mat4, depth: usize) -> Result<()> {
+        if depth > MAX_NODE_DEPTH {
+            bail!("glTF: node hierarchy is nested too deeply");
+        }
+
+        let node = array(self.doc, "nodes")
+            .get(index)
+            .ok_or_else(|| anyhow!("glTF: node {} not found", index))?;
+        if std::mem::replace(&mut self.visited[index], true) {
+            bail!("glTF: node {} is referenced more than once", index);
+        }
+        let transform = parent * node_transform(node);
+
+        if let Some(mesh_index) = self::index(node, "mesh") {
+            let mesh = array(self.doc, "meshes")
+                .get(mesh_index)
+                .ok_or_else(|| anyhow!("glTF: mesh {} not found", mesh_index))?;
+
+            let name = node["name"]
+                .as_str()
+                .or_else(|| mesh["name"].as_str())
+                .map_or_else(|| format!("mesh {}", mesh_index), |n| n.to_string());
+            let part = self.parts.len();
+            self.parts.push(name);
+
+            for primitive in array(mesh, "primitives") {
+                self.primitive(primitive, &transform, part)?;
+            }
+        }
+
+        for child in array(node, "children").iter().filter_map(|c| c.as_u64()) {
+            self.node(child as usize, &transform, depth + 1)?;
+        }
+
+        Ok(())
+    }
+
+    fn primitive(&mut self, primitive: &Value, transform: &Mat4, part: usize) -> Result<()> {
+        let mode = primitive["mode"].as_u64().unwrap_or(TRIANGLES);
+        if mode != TRIANGLES && mode != TRIANGLE_STRIP && mode != TRIANGLE_FAN {
+            // points and lines have no surface
+            return Ok(());
+        }
+
+        let positions = match index(&primitive["attributes"], "POSITION") {
+            Some(accessor) => self.accessor(accessor)?,
+            None => return Ok(()),
+        };
+        let positions = positions
+            .iter()
+            .map(|p| matmul(transform, &Vec3::new(p[0], p[1], p[2])))
+            .collect::<Vec<_>>();
+
+        let vertex_colors = match index(&primitive["attributes"], "COLOR_0") {
+            Some(accessor) => Some(self.accessor(accessor)?),
+            None => None,
+        };
+
+        let indices = match index(primitive, "indices") {
+            Some(accessor) => self.index_accessor(accessor)?,
+            None => (0..positions.len()).collect::<Vec<_>>(),
+        };
+        if let Some(i) = indices.iter().find(|i| **i >= positions.len()) {
+            bail!("glTF: vertex index {} out of range", i);
+        }
+
+        let color = index(primitive, "material")
+            .and_then(|m| array(self.doc, "materials").get(m))
+            .and_then(|m| floats(&m["pbrMetallicRoughness"]["baseColorFactor"]))
+            .filter(|c| c.len() == 4)
+            .map(|c| Vec3::new(c[0], c[1], c[2]));
+
+        // mirroring transforms flip the winding order
+        let mirrored = glm::mat4_to_mat3(transform).determinant() < 0.0;
+
+        let corners: Vec<[usize; 3]> = match mode {
+            TRIANGLE_STRIP => (0..indices.len().saturating_sub(2))
+                .map(|i| {
+                    if i % 2 == 0 {
+                        [indices[i], indices[i + 1], indices[i + 2]]
+                    } else {
+                        [indices[i + 1], indices[i], indices[i + 2]]
+                    }
+                })
+                .collect(),
+            TRIANGLE_FAN => (1..indices.len().saturating_sub(1))
+                .map(|i| [indices[0], indices[i], indices[i + 1]])
+                .collect(),
+            _ => indices.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect(),
+        };
+
+        for mut c in corners {
+            if mirrored {
+                c.swap(1, 2);
+            }
+
+            let mut triangle = Triangle::new(
+                [positions[c[0]], positions[c[1]], positions[c[2]]],
+                Vec3::new(0.0, 0.0, 0.0),
+            );
+            triangle.recalculate_normal();
+            triangle.part = part as u32;
+            triangle.set_color(color);
+
+            if let Some(colors) = &vertex_colors {
+                let rgb = |i: usize| colors.get(i).map(|c| Vec3::new(c[0], c[1], c[2]));
+                if let (Some(c0), Some(c1), Some(c2)) = (rgb(c[0]), rgb(c[1]), rgb(c[2])) {
+                    // vertex colors are multiplied with the base color
+                    let base = color.unwrap_or_else(|| Vec3::new(1.0, 1.0, 1.0));
+                    triangle.set_vertex_colors(Some([
+                        c0.component_mul(&base),
+                        c1.component_mul(&base),
+                        c2.component_mul(&base),
+                    ]));
+                }
+            }
+
+            if !triangle.has_finite_vertices() {
+                return Err(ParseError::NonFinite {
+                    triangle: self.triangles.len() as u64,
+                }
+                .into());
+            }
+            self.triangles.push(triangle);
+        }
+
+        Ok(())
+    }
+
+    // reads an accessor into a list of elements with up to 4 components
+    fn accessor(&self, index: usize) -> Result<Vec<[f32; 4]>> {
+        self.read_accessor(index, |component_type, bytes, normalized| {
+            let value = match component_type {
+                5120 => bytes[0] as i8 as f32,
+                5121 => bytes[0] as f32,
+                5122 => LittleEndian::read_i16(bytes) as f32,
+                5123 => LittleEndian::read_u16(bytes) as f32,
+                5125 => LittleEndian::read_u32(bytes) as f32,
+                _ => LittleEndian::read_f32(bytes),
+            };
+
+            if !normalized {
+                return Ok(value);
+            }
+            Ok(match component_type {
+                5120 => (value / 127.0).max(-1.0),
+                5121 => value / 255.0,
+                5122 => (value / 32767.0).max(-1.0),
+                5123 => value / 65535.0,
+                _ => value,
+            })
+        })
+    }
+
+    // reads the vertex indices exactly, floats can't represent all of the 32 bit ones
+    fn index_accessor(&self, index: usize) -> Result<Vec<usize>> {
+        let elements = self.read_accessor(index, |component_type, bytes, _| match component_type {
+            5121 => Ok(bytes[0] as usize),
+            5123 => Ok(LittleEndian::read_u16(bytes) as usize),
+            5125 => Ok(LittleEndian::read_u32(bytes) as usize),
+            ty => bail!("glTF: invalid component type {} of the indices", ty),
+        })?;
+
+        Ok(elements.iter().map(|e| e[0]).collect())
+    }
+
+    // reads the components of an accessor with the given conversion
+    fn read_accessor<T: Copy + Default>(
+        &self,
+        index: usize,
+        convert: impl Fn(u64, &[u8], bool) -> Result<T>,
+    ) -> Result<Vec<[T; 4]>> {
+        let accessor = array(self.doc, "accessors")
+            .get(index)
+            .ok_or_else(|| anyhow!("glTF: accessor {} not found", index))?;
+
+        let count = self::index(accessor, "count").unwrap_or(0);
+        let components = match accessor["type"].as_str() {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            ty => bail!("glTF: unsupported accessor type {:?}", ty),
+        };
+        let component_type = accessor["componentType"].as_u64().unwrap_or(0);
+        let component_size = match component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            ty => bail!("glTF: unsupported component type {}", ty),
+        };
+        let normalized = accessor["normalized"].as_bool().unwrap_or(false);
+
+        // accessors without a buffer view are initialized with zeros
+        let view = match self::index(accessor, "bufferView") {
+            Some(view) => array(self.doc, "bufferViews")
+                .get(view)
+                .ok_or_else(|| anyhow!("glTF: buffer view {} not found", view))?,
+            None => return Ok(vec![[T::default(); 4]; count]),
+        };
+
+        let buffer = self::index(view, "buffer")
+            .and_then(|b| self.buffers.get(b))
+            .ok_or_else(|| anyhow!("glTF: invalid buffer in buffer view"))?;
+        // offsets and strides are untrusted, overflowing ones exceed any buffer
+        let exceeds = || anyhow!("glTF: accessor {} exceeds its buffer", index);
+        let offset = self::index(view, "byteOffset")
+            .unwrap_or(0)
+            .checked_add(self::index(accessor, "byteOffset").unwrap_or(0))
+            .ok_or_else(exceeds)?;
+        let stride = self::index(view, "byteStride").unwrap_or(components * component_size);
+
+        let mut elements = Vec::with_capacity(count.min(buffer.len()));
+        for i in 0..count {
+            let mut element = [T::default(); 4];
+            for (c, value) in element.iter_mut().take(components).enumerate() {
+                let start = i
+                    .checked_mul(stride)
+                    .and_then(|s| s.checked_add(offset))
+                    .and_then(|s| s.checked_add(c * component_size))
+                    .ok_or_else(exceeds)?;
+                let bytes = start
+                    .checked_add(component_size)
+                    .and_then(|end| buffer.get(start..end))
+                    .ok_or_else(exceeds)?;
+
+                *value = convert(component_type, bytes, normalized)?;
+            }
+            elements.push(element);
+        }
+
+        Ok(elements)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::gltf::GltfParser;
+    use crate::mesh::*;
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use std::io::Cursor;
+
+    // a single triangle in the xy plane, indexed with u16
+    fn buffer() -> Vec<u8> {
+        let mut buffer = vec![];
+        for v in [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0].iter() {
+            buffer.write_f32::<LittleEndian>(*v).unwrap();
+        }
+        for i in [0, 1, 2, 0].iter() {
+            buffer.write_u16::<LittleEndian>(*i).unwrap();
+        }
+        buffer
+    }
+
+    fn json(uri: Option<&str>) -> String {
+        let uri = uri.map_or(String::new(), |uri| format!(r#""uri": "{}","#, uri));
+        format!(
+            r#"{{
+            "asset": {{ "version": "2.0" }},
+            "scene": 0,
+            "scenes": [{{ "nodes": [0] }}],
+            "nodes": [
+                {{ "name": "root", "translation": [0, 0, 1], "children": [1] }},
+                {{ "mesh": 0, "scale": [2, 1, 1], "rotation": [0, 0, 0.7071068, 0.7071068] }}
+            ],
+            "meshes": [{{ "name": "tri", "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "indices": 1, "material": 0 }}] }}],
+            "materials": [{{ "pbrMetallicRoughness": {{ "baseColorFactor": [1, 0.5, 0, 1] }} }}],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }},
+                {{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+            ],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 36, "byteLength": 6 }}
+            ],
+            "buffers": [{{ {} "byteLength": 44 }}]
+        }}"#,
+            uri
+        )
+    }
+
+    fn assert_triangle(mesh: &Mesh) {
+        let eps = 1e-3;
+        assert_eq!(mesh.len(), 1);
+        assert!((mesh[0].vertices[0] - Vec3::new(0.0, 0.0, 1000.0)).norm() < eps);
+        // scaled along x first, then rotated by 90° around z
+        assert!((mesh[0].vertices[1] - Vec3::new(0.0, 2000.0, 1000.0)).norm() < eps);
+        assert!((mesh[0].vertices[2] - Vec3::new(-1000.0, 0.0, 1000.0)).norm() < eps);
+        assert!((mesh[0].normal - Vec3::new(0.0, 0.0, 1.0)).norm() < eps);
+        assert_eq!(mesh[0].color(), Some(Vec3::new(1.0, 0.5, 0.0)));
+    }
+
+    #[test]
+    fn gltf_data_uri() {
+        let uri = format!("data:application/octet-stream;base64,{}", base64::encode(buffer()));
+        let mut parser = GltfParser::from_buf(Cursor::new(json(Some(&uri)))).unwrap();
+
+        assert_triangle(&parser.read_all().unwrap());
+        assert_eq!(parser.part_names(), &["tri".to_string()]);
+    }
+
+    #[test]
+    fn gltf_glb() {
+        let mut json = json(None).into_bytes();
+        while json.len() % 4 != 0 {
+            json.push(b' ');
+        }
+        let mut bin = buffer();
+        bin.resize(44, 0);
+
+        let mut glb = b"glTF".to_vec();
+        glb.write_u32::<LittleEndian>(2).unwrap();
+        glb.write_u32::<LittleEndian>((12 + 8 + json.len() + 8 + bin.len()) as u32)
+            .unwrap();
+        glb.write_u32::<LittleEndian>(json.len() as u32).unwrap();
+        glb.extend_from_slice(b"JSON");
+        glb.extend_from_slice(&json);
+        glb.write_u32::<LittleEndian>(bin.len() as u32).unwrap();
+        glb.extend_from_slice(b"BIN\0");
+        glb.extend_from_slice(&bin);
+
+        let mut parser = GltfParser::from_buf(Cursor::new(glb)).unwrap();
+        assert_triangle(&parser.read_all().unwrap());
+    }
+
+    // indices above 2^24 aren't representable as f32
+    #[test]
+    fn gltf_large_indices() {
+        let mut buffer = vec![];
+        for v in [0.0f32; 9].iter() {
+            buffer.write_f32::<LittleEndian>(*v).unwrap();
+        }
+        for i in [0u32, 1, 16_777_217].iter() {
+            buffer.write_u32::<LittleEndian>(*i).unwrap();
+        }
+
+        let gltf = |index_type: u32| {
+            format!(
+                r#"{{
+                "asset": {{ "version": "2.0" }},
+                "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "indices": 1 }}] }}],
+                "nodes": [{{ "mesh": 0 }}],
+                "accessors": [
+                    {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }},
+                    {{ "bufferView": 1, "componentType": {}, "count": 3, "type": "SCALAR" }}
+                ],
+                "bufferViews": [
+                    {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                    {{ "buffer": 0, "byteOffset": 36, "byteLength": 12 }}
+                ],
+                "buffers": [{{ "uri": "data:application/octet-stream;base64,{}", "byteLength": 48 }}]
+            }}"#,
+                index_type,
+                base64::encode(&buffer)
+            )
+        };
+
+        let err = GltfParser::from_buf(Cursor::new(gltf(5125))).err().unwrap();
+        assert!(err.to_string().contains("16777217"), "{}", err);
+
+        // float indices are invalid
+        let err = GltfParser::from_buf(Cursor::new(gltf(5126))).err().unwrap();
+        assert!(err.to_string().contains("indices"), "{}", err);
+    }
+
+    #[test]
+    fn gltf_overflowing_accessor() {
+        let gltf = |view: &str, accessor: &str| {
+            format!(
+                r#"{{
+                "asset": {{ "version": "2.0" }},
+                "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }} }}] }}],
+                "nodes": [{{ "mesh": 0 }}],
+                "accessors": [{{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"{} }}],
+                "bufferViews": [{{ "buffer": 0, "byteLength": 36{} }}],
+                "buffers": [{{ "uri": "data:application/octet-stream;base64,{}", "byteLength": 36 }}]
+            }}"#,
+                accessor,
+                view,
+                base64::encode([0u8; 36])
+            )
+        };
+
+        for (view, accessor) in [
+            ("", r#", "byteOffset": 18446744073709551615"#),
+            (r#", "byteOffset": 1"#, r#", "byteOffset": 18446744073709551615"#),
+            (r#", "byteStride": 9223372036854775808"#, ""),
+            (r#", "byteStride": 18446744073709551615"#, r#", "byteOffset": 4"#),
+        ] {
+            let err = GltfParser::from_buf(Cursor::new(gltf(view, accessor))).err().unwrap();
+            assert!(err.to_string().contains("exceeds its buffer"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn gltf_shared_nodes() {
+        let gltf = |nodes: &str| {
+            format!(
+                r#"{{
+                "asset": {{ "version": "2.0" }},
+                "scenes": [{{ "nodes": [0] }}],
+                "nodes": {}
+            }}"#,
+                nodes
+            )
+        };
+
+        // a node listed twice, a child of two nodes and a cycle
+        for nodes in [
+            r#"[{ "children": [1, 1] }, {}]"#,
+            r#"[{ "children": [1, 2] }, { "children": [2] }, {}]"#,
+            r#"[{ "children": [1] }, { "children": [0] }]"#,
+        ] {
+            let err = GltfParser::from_buf(Cursor::new(gltf(nodes))).err().unwrap();
+            assert!(err.to_string().contains("more than once"), "{}", err);
+        }
+
+        assert!(GltfParser::from_buf(Cursor::new(gltf(r#"[{ "children": [1, 2] }, {}, {}]"#))).is_ok());
+    }
+
+    #[test]
+    fn gltf_external_buffer() {
+        assert!(GltfParser::from_buf(Cursor::new(json(Some("triangle.bin")))).is_err());
+    }
+}
@@ -0,0 +1,308 @@
+use crate::indexed::Edge;
+use crate::mesh::*;
+use glm::DVec3;
+use std::collections::HashMap;
+
+/// The convex hull of a point cloud, e.g. the vertices of a model
+#[derive(Debug, Clone)]
+pub struct ConvexHull {
+    pub vertices: Vec<Vec3>,
+    /// wound counterclockwise seen from outside
+    pub faces: Vec<[u32; 3]>,
+}
+
+impl ConvexHull {
+    /// Quickhull, None if the points are coplanar (or there are fewer than four of them)
+    pub fn new(points: &[Vec3]) -> Option<Self> {
+        let points: Vec<DVec3> = points.iter().map(|p| glm::convert(*p)).collect();
+
+        // points closer than this to a plane are considered to lie on it
+        let scale = points.iter().fold(0.0f64, |m, p| m.max(p.amax()));
+        let epsilon = scale * 1e-9;
+
+        let mut hull = Quickhull {
+            points: &points,
+            faces: vec![],
+            edges: HashMap::new(),
+            epsilon,
+        };
+
+        let simplex = hull.initial_simplex()?;
+        let remaining: Vec<u32> = (0..points.len() as u32).filter(|i| !simplex.contains(i)).collect();
+        let faces: Vec<usize> = (0..hull.faces.len()).collect();
+        hull.assign(&remaining, &faces);
+
+        let mut pending = faces;
+        while let Some(face) = pending.pop() {
+            if hull.faces[face].alive && !hull.faces[face].outside.is_empty() {
+                pending.extend(hull.expand(face));
+            }
+        }
+
+        Some(hull.finish())
+    }
+
+    /// Hull of the vertices of a model
+    pub fn from_mesh(mesh: impl IntoIterator<Item = Triangle>) -> Option<Self> {
+        let points: Vec<Vec3> = mesh.into_iter().flat_map(|t| t.vertices).collect();
+        Self::new(&points)
+    }
+
+    pub fn triangle(&self, face: &[u32; 3]) -> [Vec3; 3] {
+        face.map(|i| self.vertices[i as usize])
+    }
+}
+
+struct HullFace {
+    indices: [u32; 3],
+    normal: DVec3,
+    offset: f64,
+    /// points in front of the face not yet part of the hull
+    outside: Vec<u32>,
+    alive: bool,
+    /// seen from the point being added, such faces are replaced
+    visible: bool,
+}
+
+struct Quickhull<'a> {
+    points: &'a [DVec3],
+    faces: Vec<HullFace>,
+    /// face on the left of each directed edge
+    edges: HashMap<Edge, usize>,
+    epsilon: f64,
+}
+
+impl<'a> Quickhull<'a> {
+    fn distance(&self, face: usize, point: u32) -> f64 {
+        let face = &self.faces[face];
+        face.normal.dot(&self.points[point as usize]) - face.offset
+    }
+
+    // the tetrahedron spanned by the extreme points
+    fn initial_simplex(&mut self) -> Option<[u32; 4]> {
+        let points = self.points;
+        if points.len() < 4 {
+            return None;
+        }
+
+        let mut extremes = vec![];
+        for axis in 0..3 {
+            let by_axis = |a: &&DVec3, b: &&DVec3| a[axis].total_cmp(&b[axis]);
+            extremes.push(points.iter().enumerate().min_by(|a, b| by_axis(&a.1, &b.1))?.0);
+            extremes.push(points.iter().enumerate().max_by(|a, b| by_axis(&a.1, &b.1))?.0);
+        }
+
+        let mut p0 = extremes[0];
+        let mut p1 = extremes[1];
+        for &a in &extremes {
+            for &b in &extremes {
+                if glm::distance(&points[a], &points[b]) > glm::distance(&points[p0], &points[p1]) {
+                    p0 = a;
+                    p1 = b;
+                }
+            }
+        }
+
+        let direction = (points[p1] - points[p0]).normalize();
+        let line_distance = |p: &DVec3| (p - points[p0]).cross(&direction).norm();
+        let p2 = farthest(points, line_distance)?;
+        if line_distance(&points[p2]) <= self.epsilon {
+            return None;
+        }
+
+        let normal = (points[p1] - points[p0]).cross(&(points[p2] - points[p0])).normalize();
+        let plane_distance = |p: &DVec3| normal.dot(&(p - points[p0])).abs();
+        let p3 = farthest(points, plane_distance)?;
+        if plane_distance(&points[p3]) <= self.epsilon {
+            return None;
+        }
+
+        let simplex = [p0 as u32, p1 as u32, p2 as u32, p3 as u32];
+        for skipped in 0..4 {
+            let mut indices = [0; 3];
+            let mut n = 0;
+            for (i, index) in simplex.iter().enumerate() {
+                if i != skipped {
+                    indices[n] = *index;
+                    n += 1;
+                }
+            }
+
+            // the skipped corner lies behind the face
+            let [a, b, c] = indices.map(|i| points[i as usize]);
+            if (b - a).cross(&(c - a)).dot(&(points[simplex[skipped] as usize] - a)) > 0.0 {
+                indices.swap(1, 2);
+            }
+            self.add_face(indices);
+        }
+
+        Some(simplex)
+    }
+
+    fn add_face(&mut self, indices: [u32; 3]) -> usize {
+        let [a, b, c] = indices.map(|i| self.points[i as usize]);
+        let normal = (b - a).cross(&(c - a)).normalize();
+
+        let id = self.faces.len();
+        self.faces.push(HullFace {
+            indices,
+            normal,
+            offset: normal.dot(&a),
+            outside: vec![],
+            alive: true,
+            visible: false,
+        });
+        for (i, &index) in indices.iter().enumerate() {
+            self.edges.insert((index, indices[(i + 1) % 3]), id);
+        }
+
+        id
+    }
+
+    fn remove_face(&mut self, face: usize) {
+        let indices = self.faces[face].indices;
+        for (i, &index) in indices.iter().enumerate() {
+            self.edges.remove(&(index, indices[(i + 1) % 3]));
+        }
+        self.faces[face].alive = false;
+        self.faces[face].outside = vec![];
+    }
+
+    // points inside all of the faces are dropped
+    fn assign(&mut self, points: &[u32], faces: &[usize]) {
+        for &point in points {
+            if let Some(&face) = faces.iter().find(|f| self.distance(**f, point) > self.epsilon) {
+                self.faces[face].outside.push(point);
+            }
+        }
+    }
+
+    // adds the farthest outside point of the face to the hull and returns the new faces
+    fn expand(&mut self, face: usize) -> Vec<usize> {
+        let apex = *self.faces[face]
+            .outside
+            .iter()
+            .max_by(|a, b| self.distance(face, **a).total_cmp(&self.distance(face, **b)))
+            .unwrap();
+
+        // faces seen from the apex, bordered by the horizon
+        let mut visible = vec![face];
+        self.faces[face].visible = true;
+        let mut horizon = vec![];
+        let mut i = 0;
+        while i < visible.len() {
+            let indices = self.faces[visible[i]].indices;
+            for (j, &a) in indices.iter().enumerate() {
+                let b = indices[(j + 1) % 3];
+                match self.edges.get(&(b, a)) {
+                    Some(&neighbour) if self.faces[neighbour].visible => {}
+                    Some(&neighbour) if self.distance(neighbour, apex) > self.epsilon => {
+                        self.faces[neighbour].visible = true;
+                        visible.push(neighbour);
+                    }
+                    _ => horizon.push((a, b)),
+                }
+            }
+            i += 1;
+        }
+
+        let mut orphans = vec![];
+        for &face in &visible {
+            orphans.extend(self.faces[face].outside.iter().filter(|p| **p != apex));
+            self.remove_face(face);
+        }
+
+        let faces: Vec<usize> = horizon.iter().map(|(a, b)| self.add_face([*a, *b, apex])).collect();
+        self.assign(&orphans, &faces);
+
+        faces
+    }
+
+    fn finish(self) -> ConvexHull {
+        let mut remap = HashMap::new();
+        let mut vertices = vec![];
+        let mut faces = vec![];
+
+        for face in self.faces.iter().filter(|f| f.alive) {
+            faces.push(face.indices.map(|i| {
+                *remap.entry(i).or_insert_with(|| {
+                    vertices.push(glm::convert(self.points[i as usize]));
+                    vertices.len() as u32 - 1
+                })
+            }));
+        }
+
+        ConvexHull { vertices, faces }
+    }
+}
+
+fn farthest(points: &[DVec3], distance: impl Fn(&DVec3) -> f64) -> Option<usize> {
+    points
+        .iter()
+        .enumerate()
+        .max_by(|a, b| distance(a.1).total_cmp(&distance(b.1)))
+        .map(|(i, _)| i)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::hull::ConvexHull;
+    use crate::mesh::*;
+
+    // every point lies behind or on every face
+    fn assert_encloses(hull: &ConvexHull, points: &[Vec3]) {
+        for face in &hull.faces {
+            let [a, b, c] = hull.triangle(face);
+            let normal = (b - a).cross(&(c - a)).normalize();
+            for p in points {
+                assert!(normal.dot(&(p - a)) < 1e-4, "{:?} outside of {:?}", p, face);
+            }
+        }
+    }
+
+    #[test]
+    fn cube_hull() {
+        let mut points = vec![];
+        for i in 0..8 {
+            points.push(Vec3::new((i & 1) as f32, (i >> 1 & 1) as f32, (i >> 2 & 1) as f32));
+        }
+        // inside, on a face and duplicated corners
+        points.push(Vec3::new(0.5, 0.5, 0.5));
+        points.push(Vec3::new(0.5, 0.5, 1.0));
+        points.extend(points.clone());
+
+        let hull = ConvexHull::new(&points).unwrap();
+        assert_eq!(hull.vertices.len(), 8);
+        assert_eq!(hull.faces.len(), 12);
+        assert_encloses(&hull, &points);
+    }
+
+    #[test]
+    fn sphere_hull() {
+        let mut points = vec![];
+        for i in 0..20 {
+            for j in 0..20 {
+                let (theta, phi) = (i as f32 * 0.157, j as f32 * 0.314);
+                let r = if (i + j) % 3 == 0 { 0.5 } else { 1.0 };
+                points.push(Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()) * r);
+            }
+        }
+
+        let hull = ConvexHull::new(&points).unwrap();
+        assert_encloses(&hull, &points);
+        // closed, hence Euler's formula holds
+        assert_eq!(hull.vertices.len() as i64 - hull.faces.len() as i64 / 2, 2);
+    }
+
+    #[test]
+    fn flat_hull() {
+        let points = [
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+        ];
+        assert!(ConvexHull::new(&points).is_none());
+        assert!(ConvexHull::new(&points[..3]).is_none());
+    }
+}
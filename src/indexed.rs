@@ -0,0 +1,303 @@
+use crate::aabb::AABB;
+use crate::mesh::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Distance below which vertices are considered coincident by default, relative to the diagonal of
+/// the bounding box such that it doesn't depend on the unit of the model
+pub const DEFAULT_TOLERANCE: f32 = 1e-6;
+
+/// An undirected edge, the lower vertex index comes first
+pub type Edge = (u32, u32);
+
+/// A triangle of an indexed mesh, the attributes are the ones of the original triangle
+#[derive(Debug, Clone, PartialEq)]
+pub struct Face {
+    pub indices: [u32; 3],
+    pub normal: Vec3,
+    pub part: u32,
+    /// shared with the original triangle
+    pub attributes: Option<Arc<Attributes>>,
+}
+
+impl Face {
+    pub fn edges(&self) -> [Edge; 3] {
+        let [a, b, c] = self.indices;
+        [edge(a, b), edge(b, c), edge(c, a)]
+    }
+
+    /// Whether welding collapsed the triangle to a line or a point
+    pub fn is_degenerate(&self) -> bool {
+        let [a, b, c] = self.indices;
+        a == b || b == c || c == a
+    }
+}
+
+/// A mesh whose triangles share their vertices
+/// Coincident vertices of the triangle soup are welded, which provides the adjacency
+/// information needed to analyze the topology of the model.
+/// Degenerate faces are kept such that converting back yields the same triangles.
+#[derive(Debug, Clone, Default)]
+pub struct IndexedMesh {
+    pub vertices: Vec<Vec3>,
+    pub faces: Vec<Face>,
+}
+
+impl IndexedMesh {
+    /// Welds vertices closer than the default tolerance scaled to the size of the mesh
+    pub fn welded(mesh: &Mesh) -> Self {
+        Self::from_mesh(mesh, default_tolerance(mesh))
+    }
+
+    /// Welds vertices closer than the tolerance, a tolerance of zero welds identical vertices only
+    pub fn from_mesh(mesh: &Mesh, tolerance: f32) -> Self {
+        let mut welder = Welder::new(tolerance);
+        let mut faces = Vec::with_capacity(mesh.len());
+
+        for t in mesh {
+            faces.push(Face {
+                indices: [
+                    welder.insert(t.vertices[0]),
+                    welder.insert(t.vertices[1]),
+                    welder.insert(t.vertices[2]),
+                ],
+                normal: t.normal,
+                part: t.part,
+                attributes: t.attributes,
+            });
+        }
+
+        Self {
+            vertices: welder.vertices,
+            faces,
+        }
+    }
+
+    /// Expands the mesh back into a triangle soup
+    pub fn to_mesh(&self) -> Mesh {
+        Mesh::new(self.faces.iter().map(|f| self.triangle(f)).collect())
+    }
+
+    pub fn triangle(&self, face: &Face) -> Triangle {
+        let [a, b, c] = face.indices;
+
+        let mut triangle = Triangle::new(
+            [
+                self.vertices[a as usize],
+                self.vertices[b as usize],
+                self.vertices[c as usize],
+            ],
+            face.normal,
+        );
+        triangle.part = face.part;
+        triangle.attributes = face.attributes.clone();
+
+        triangle
+    }
+
+    /// The faces sharing each edge
+    pub fn edge_faces(&self) -> HashMap<Edge, Vec<usize>> {
+        let mut edges: HashMap<Edge, Vec<usize>> = HashMap::with_capacity(self.faces.len() * 3 / 2);
+
+        for (i, face) in self.faces.iter().enumerate() {
+            if face.is_degenerate() {
+                continue;
+            }
+            for edge in &face.edges() {
+                edges.entry(*edge).or_default().push(i);
+            }
+        }
+
+        edges
+    }
+
+    /// The faces around each vertex
+    pub fn vertex_faces(&self) -> Vec<Vec<usize>> {
+        let mut faces = vec![vec![]; self.vertices.len()];
+
+        for (i, face) in self.faces.iter().enumerate() {
+            for index in &face.indices {
+                faces[*index as usize].push(i);
+            }
+        }
+
+        faces
+    }
+}
+
+/// The absolute welding tolerance of a mesh, zero for empty meshes
+pub fn default_tolerance(mesh: &Mesh) -> f32 {
+    let diagonal = AABB::from_mesh(mesh).size().norm();
+    if diagonal.is_finite() {
+        diagonal * DEFAULT_TOLERANCE
+    } else {
+        0.0
+    }
+}
+
+fn edge(a: u32, b: u32) -> Edge {
+    (a.min(b), a.max(b))
+}
+
+// spatial hash with cells twice as large as the tolerance, hence coincident vertices are found in the cell
+// of the vertex or the neighbouring cells on the sides the vertex is closer to
+struct Welder {
+    tolerance: f32,
+    // the last vertex inserted into each cell, the others are chained
+    cells: HashMap<[i64; 3], u32>,
+    next: Vec<u32>,
+    vertices: Vec<Vec3>,
+}
+
+const END_OF_CHAIN: u32 = u32::MAX;
+
+impl Welder {
+    fn new(tolerance: f32) -> Self {
+        Self {
+            tolerance,
+            cells: HashMap::new(),
+            next: vec![],
+            vertices: vec![],
+        }
+    }
+
+    // returns the index of the vertex, coincident vertices are merged into the one inserted earlier
+    fn insert(&mut self, vertex: Vec3) -> u32 {
+        let cell = self.cell(&vertex);
+
+        // most vertices are shared by several triangles and found in their own cell
+        if let Some(index) = self.find(&cell, &vertex) {
+            return index;
+        }
+
+        if self.tolerance > 0.0 {
+            for neighbour in self.neighbours(&vertex, cell) {
+                if let Some(index) = self.find(&neighbour, &vertex) {
+                    return index;
+                }
+            }
+        }
+
+        let index = self.vertices.len() as u32;
+        self.vertices.push(vertex);
+        self.next.push(self.cells.insert(cell, index).unwrap_or(END_OF_CHAIN));
+
+        index
+    }
+
+    fn find(&self, cell: &[i64; 3], vertex: &Vec3) -> Option<u32> {
+        let mut index = *self.cells.get(cell)?;
+
+        while index != END_OF_CHAIN {
+            if glm::distance(&self.vertices[index as usize], vertex) <= self.tolerance {
+                return Some(index);
+            }
+            index = self.next[index as usize];
+        }
+
+        None
+    }
+
+    fn cell(&self, vertex: &Vec3) -> [i64; 3] {
+        if self.tolerance > 0.0 {
+            let cell = |c: f32| (c / (2.0 * self.tolerance)).floor() as i64;
+            [cell(vertex.x), cell(vertex.y), cell(vertex.z)]
+        } else {
+            // identical vertices only, -0.0 and 0.0 are the same
+            let cell = |c: f32| (c + 0.0).to_bits() as i64;
+            [cell(vertex.x), cell(vertex.y), cell(vertex.z)]
+        }
+    }
+
+    // the 7 cells next to the corner of the cell closest to the vertex
+    fn neighbours(&self, vertex: &Vec3, cell: [i64; 3]) -> impl Iterator<Item = [i64; 3]> {
+        let side = |c: f32| {
+            let scaled = c / (2.0 * self.tolerance);
+            if scaled - scaled.floor() < 0.5 {
+                -1
+            } else {
+                1
+            }
+        };
+        let sides = [side(vertex.x), side(vertex.y), side(vertex.z)];
+
+        (1..8).map(move |i| {
+            [
+                cell[0] + (i & 1) * sides[0],
+                cell[1] + (i >> 1 & 1) * sides[1],
+                cell[2] + (i >> 2 & 1) * sides[2],
+            ]
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::indexed::{default_tolerance, IndexedMesh};
+    use crate::mesh::*;
+    use crate::transform::Transform;
+
+    fn quad(offset: f32) -> Mesh {
+        let v = [
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ];
+        let shift = Vec3::new(offset, 0.0, 0.0);
+
+        let mut first = Triangle::new([v[0], v[1], v[2]], Vec3::new(0.0, 0.0, 1.0));
+        first.set_color(Some(Vec3::new(1.0, 0.0, 0.0)));
+        let mut second = Triangle::new([v[0] + shift, v[2] + shift, v[3]], Vec3::new(0.0, 0.0, 1.0));
+        second.part = 1;
+
+        Mesh::new(vec![first, second])
+    }
+
+    #[test]
+    fn weld_vertices() {
+        let indexed = IndexedMesh::from_mesh(&quad(0.0), 0.0);
+        assert_eq!(indexed.vertices.len(), 4);
+        assert_eq!(indexed.faces[1].indices, [0, 2, 3]);
+
+        // the shared diagonal
+        let edges = indexed.edge_faces();
+        assert_eq!(edges.len(), 5);
+        assert_eq!(edges[&(0, 2)], vec![0, 1]);
+
+        assert_eq!(indexed.vertex_faces()[0], vec![0, 1]);
+        assert_eq!(indexed.vertex_faces()[1], vec![0]);
+    }
+
+    #[test]
+    fn weld_tolerance() {
+        // vertices which are off by a rounding error
+        let mesh = quad(1e-6);
+        assert_eq!(IndexedMesh::from_mesh(&mesh, 0.0).vertices.len(), 6);
+        assert_eq!(IndexedMesh::welded(&mesh).vertices.len(), 4);
+
+        // the tolerance follows the size of the model, e.g. meters instead of millimeters
+        let mut mesh = quad(1e-9);
+        Transform::scaling(Vec3::repeat(1e-3)).unwrap().apply_mesh(&mut mesh);
+        assert_eq!(IndexedMesh::welded(&mesh).vertices.len(), 4);
+        assert!(default_tolerance(&mesh) < 1e-8);
+        assert_eq!(default_tolerance(&Mesh::new(vec![])), 0.0);
+
+        // -0.0 and 0.0 are identical
+        let mut mesh = quad(0.0);
+        let mut triangle = mesh[1].clone();
+        triangle.vertices[0].x = -0.0;
+        mesh.push(triangle);
+        assert_eq!(IndexedMesh::from_mesh(&mesh, 0.0).vertices.len(), 4);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mesh = quad(0.0);
+        let back = IndexedMesh::welded(&mesh).to_mesh();
+
+        assert_eq!(back.len(), mesh.len());
+        assert_eq!(back[0], mesh[0]);
+        assert_eq!(back[1], mesh[1]);
+    }
+}
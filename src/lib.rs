@@ -1,8 +1,29 @@
 pub mod aabb;
+pub mod amf;
+pub mod components;
+pub mod compression;
+pub mod decimate;
+pub mod defects;
 pub mod encoder;
 pub mod ffi;
+pub mod gltf;
+pub mod hull;
+pub mod indexed;
+pub mod loader;
 pub mod mesh;
+pub mod mmap;
+pub mod obj;
+pub mod off;
+pub mod orientation;
+pub mod parallel;
 pub mod parser;
 pub mod picture;
+pub mod ply;
+pub mod pose;
 pub mod rasterbackend;
+pub mod smooth;
+pub mod stats;
+pub mod stream;
+pub mod threemf;
+pub mod transform;
 pub mod zbuffer;
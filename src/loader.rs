@@ -0,0 +1,417 @@
+use crate::aabb::AABB;
+use crate::amf::AmfParser;
+use crate::compression::{decompress, Compression};
+use crate::gltf::GltfParser;
+use crate::mesh::{Mesh, MeshSource};
+use crate::mmap::MappedParser;
+use crate::obj::ObjParser;
+use crate::off::OffParser;
+use crate::parser::{binary_stl_size, stl_type_of, Parser, Recovery};
+use crate::ply::PlyParser;
+use crate::stream::{Buffered, Peeked, StreamParser};
+use crate::threemf::ThreeMfParser;
+use crate::transform::Axis;
+use anyhow::Result;
+use std::fs;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+// enough to see past comments and the xml declaration
+const SNIFF_LENGTH: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Stl,
+    Obj,
+    ThreeMf,
+    Ply,
+    Gltf,
+    Amf,
+    Off,
+}
+
+/// Options for opening a model
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    pub recalculate_normals: bool,
+    /// model of a zip archive holding several models, defaults to the largest file
+    pub entry: Option<String>,
+    /// recovers what's left of malformed binary STL files instead of rejecting them
+    pub lenient: bool,
+    /// memory maps binary STL files instead of reading them through a buffer
+    pub memory_map: bool,
+}
+
+impl LoadOptions {
+    fn recovery(&self) -> Recovery {
+        if self.lenient {
+            Recovery::Lenient
+        } else {
+            Recovery::Strict
+        }
+    }
+}
+
+/// Any seekable input
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+type OpenFn = fn(Box<dyn ReadSeek>, &LoadOptions) -> Result<Box<dyn MeshSource>>;
+
+struct Reader {
+    format: Format,
+    /// text formats might be confused with the free text header of binary STL files
+    text: bool,
+    matches: fn(&[u8]) -> bool,
+    open: OpenFn,
+}
+
+// binary STL has no magic and is the fallback if none of the readers match
+const READERS: &[Reader] = &[
+    Reader {
+        format: Format::Gltf,
+        text: false,
+        matches: |header| header.starts_with(b"glTF"),
+        open: |inner, _| Ok(Box::new(GltfParser::from_buf(inner)?)),
+    },
+    Reader {
+        format: Format::Gltf,
+        text: true,
+        matches: |header| text(header).starts_with('{'),
+        open: |inner, _| Ok(Box::new(GltfParser::from_buf(inner)?)),
+    },
+    Reader {
+        format: Format::ThreeMf,
+        text: false,
+        matches: |header| header.starts_with(ZIP_MAGIC) && !is_zipped_amf(header),
+        open: |inner, _| Ok(Box::new(ThreeMfParser::from_buf(inner)?)),
+    },
+    Reader {
+        format: Format::Amf,
+        // zipped or xml
+        text: false,
+        matches: |header| {
+            let text = text(header);
+            is_zipped_amf(header) || text.starts_with("<?xml") || text.starts_with("<amf")
+        },
+        open: |inner, _| Ok(Box::new(AmfParser::from_buf(inner)?)),
+    },
+    Reader {
+        format: Format::Ply,
+        text: true,
+        matches: |header| keyword(text(header)) == "ply",
+        open: |inner, _| Ok(Box::new(PlyParser::from_buf(inner)?)),
+    },
+    Reader {
+        format: Format::Off,
+        text: true,
+        // [ST][C][N][4][n]OFF
+        matches: |header| {
+            let text = text(header);
+            let keyword = keyword(text);
+            keyword.ends_with("OFF") && keyword.chars().all(|c| "STCN4nOF".contains(c))
+        },
+        open: |inner, _| Ok(Box::new(OffParser::from_buf(inner)?)),
+    },
+    Reader {
+        format: Format::Stl,
+        text: true,
+        matches: |header| keyword(text(header)) == "solid",
+        open: open_stl,
+    },
+    Reader {
+        format: Format::Obj,
+        text: true,
+        // OBJ files start with comments or statements
+        matches: |header| {
+            let text = text(header);
+            let keyword = keyword(text);
+            keyword.starts_with('#') || ["v", "vn", "vt", "f", "o", "g", "s", "mtllib", "usemtl"].contains(&keyword)
+        },
+        open: |inner, options| Ok(Box::new(ObjParser::from_buf(inner, options.recalculate_normals)?)),
+    },
+];
+
+const FALLBACK: Reader = Reader {
+    format: Format::Stl,
+    text: false,
+    matches: |_| true,
+    open: open_stl,
+};
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+fn open_stl(inner: Box<dyn ReadSeek>, options: &LoadOptions) -> Result<Box<dyn MeshSource>> {
+    Ok(Box::new(Parser::with_recovery(
+        inner,
+        options.recalculate_normals,
+        options.recovery(),
+    )?))
+}
+
+fn find_reader(header: &[u8], size: Option<u64>) -> &'static Reader {
+    // the header of binary STL files is free text, the size implied by its triangle count gives them away
+    let binary_stl = size.is_some() && size == binary_stl_size(header);
+
+    READERS
+        .iter()
+        .filter(|r| !(binary_stl && r.text))
+        .find(|r| (r.matches)(header))
+        .unwrap_or(&FALLBACK)
+}
+
+impl Format {
+    /// Guesses the format from the first bytes of the file, anything unknown is treated as STL
+    pub fn sniff(header: &[u8]) -> Self {
+        find_reader(header, None).format
+    }
+
+    /// Like sniff, the size of the file tells binary STL files apart from text formats
+    pub fn sniff_with_size(header: &[u8], size: u64) -> Self {
+        find_reader(header, Some(size)).format
+    }
+
+    /// Sniffs the format of a file, compressed files are decompressed first
+    pub fn from_file(path: &str) -> Result<Self> {
+        let mut inner = decompress(Box::new(fs::File::open(path)?), None)?;
+        let size = inner.seek(SeekFrom::End(0))?;
+        inner.seek(SeekFrom::Start(0))?;
+
+        let mut header = Vec::with_capacity(SNIFF_LENGTH);
+        inner.take(SNIFF_LENGTH as u64).read_to_end(&mut header)?;
+
+        Ok(Self::sniff_with_size(&header, size))
+    }
+
+    /// The up axis prescribed by the specification or the common practice of the format
+    pub fn up_axis(self) -> Option<Axis> {
+        match self {
+            Format::Gltf => Some(Axis::Y),
+            Format::Stl | Format::ThreeMf | Format::Amf => Some(Axis::Z),
+            Format::Obj | Format::Ply | Format::Off => None,
+        }
+    }
+}
+
+/// Opens the model with the reader matching its content, returns the source and the format found
+/// Compressed input is decompressed into memory first.
+pub fn open_buf(inner: Box<dyn ReadSeek>, options: &LoadOptions) -> Result<(Box<dyn MeshSource>, Format)> {
+    let mut inner = decompress(inner, options.entry.as_deref())?;
+    let size = inner.seek(SeekFrom::End(0))?;
+    inner.seek(SeekFrom::Start(0))?;
+
+    let mut header = Vec::with_capacity(SNIFF_LENGTH);
+    (&mut inner).take(SNIFF_LENGTH as u64).read_to_end(&mut header)?;
+    inner.seek(SeekFrom::Start(0))?;
+
+    let reader = find_reader(&header, Some(size));
+    Ok(((reader.open)(inner, options)?, reader.format))
+}
+
+/// Opens the model file with the reader matching its content, returns the source and the format found
+pub fn open(path: &str, options: &LoadOptions) -> Result<(Box<dyn MeshSource>, Format)> {
+    if options.memory_map {
+        if let Some(parser) = MappedParser::from_file(path, options.recalculate_normals, options.recovery())? {
+            return Ok((Box::new(parser), Format::Stl));
+        }
+    }
+
+    open_buf(Box::new(fs::File::open(path)?), options)
+}
+
+/// Opens a model from a stream which can only be read once (e.g. stdin)
+/// STL files are streamed, other formats, compressed input and STL files whose first bytes don't tell ascii
+/// from binary are read into memory first.
+pub fn open_stream(inner: Box<dyn Read>, options: &LoadOptions) -> Result<(Box<dyn MeshSource>, Format)> {
+    let mut inner = Peeked::new(inner, SNIFF_LENGTH)?;
+
+    let header = inner.prefix();
+    if Compression::sniff(header) == Compression::None && Format::sniff(header) == Format::Stl {
+        // the size is only known if the whole stream fits into the prefix
+        let size = Some(header.len() as u64).filter(|&len| len < SNIFF_LENGTH as u64);
+        if let Some(stl_type) = stl_type_of(header, size) {
+            let parser = StreamParser::new(inner, stl_type, options.recalculate_normals, options.recovery())?;
+            return Ok((Box::new(parser), Format::Stl));
+        }
+    }
+
+    let mut data = vec![];
+    inner.read_to_end(&mut data)?;
+    open_buf(Box::new(Cursor::new(data)), options)
+}
+
+/// Makes the source multi-pass, single-pass sources are buffered in memory
+pub fn multi_pass(source: Box<dyn MeshSource>) -> Box<dyn MeshSource> {
+    if source.is_multi_pass() {
+        source
+    } else {
+        Box::new(Buffered::new(source))
+    }
+}
+
+/// Reads the whole model into memory
+pub fn load_mesh(path: &str, recalculate_normals: bool) -> Result<Mesh> {
+    let options = LoadOptions {
+        recalculate_normals,
+        ..LoadOptions::default()
+    };
+
+    open(path, &options)?.0.read_all()
+}
+
+/// Reads the whole model into memory and computes its AABB in the same pass
+pub fn load_mesh_with_bounds(path: &str, recalculate_normals: bool) -> Result<(Mesh, AABB)> {
+    let options = LoadOptions {
+        recalculate_normals,
+        ..LoadOptions::default()
+    };
+
+    open(path, &options)?.0.read_all_with_bounds()
+}
+
+// the header as text without leading whitespace and byte order mark, empty if it isn't utf8
+fn text(header: &[u8]) -> &str {
+    let text = match std::str::from_utf8(header) {
+        Ok(text) => text,
+        // the header might end within a character
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&header[..e.valid_up_to()]).unwrap_or_default(),
+        Err(_) => "",
+    };
+    text.trim_start_matches('\u{feff}').trim_start()
+}
+
+fn keyword(text: &str) -> &str {
+    text.split_whitespace().next().unwrap_or_default()
+}
+
+// 3MF packages and compressed AMF files are both zip archives,
+// the name of the first entry tells them apart
+fn is_zipped_amf(header: &[u8]) -> bool {
+    if !header.starts_with(ZIP_MAGIC) {
+        return false;
+    }
+
+    let name_length = header
+        .get(26..28)
+        .map_or(0, |l| u16::from_le_bytes([l[0], l[1]]) as usize);
+    let name = header.get(30..30 + name_length).unwrap_or_default();
+    name.to_ascii_lowercase().ends_with(b".amf")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::loader::{multi_pass, open_buf, open_stream, Format, LoadOptions};
+    use std::io::Cursor;
+
+    const TRI_BIN: &[u8] = include_bytes!("test_models/triangle.stl");
+
+    #[test]
+    fn sniff_formats() {
+        assert_eq!(Format::sniff(b"glTF\x02\x00\x00\x00"), Format::Gltf);
+        assert_eq!(Format::sniff(b"  {\"asset\": {}}"), Format::Gltf);
+        assert_eq!(Format::sniff(b"ply\nformat ascii 1.0\n"), Format::Ply);
+        assert_eq!(Format::sniff(b"COFF\n3 1 0\n"), Format::Off);
+        assert_eq!(Format::sniff(b"<?xml version=\"1.0\"?>\n<amf>"), Format::Amf);
+        assert_eq!(Format::sniff(b"# exported\nv 0 0 0\n"), Format::Obj);
+        assert_eq!(Format::sniff(b"solid cube\n"), Format::Stl);
+        assert_eq!(Format::sniff(&[0u8; 84]), Format::Stl);
+    }
+
+    #[test]
+    fn sniff_binary_stl_with_text_header() {
+        for prefix in [&b"# exported"[..], b"{ exported }"] {
+            let mut stl = TRI_BIN.to_vec();
+            stl[..prefix.len()].copy_from_slice(prefix);
+
+            assert_eq!(Format::sniff_with_size(&stl, stl.len() as u64), Format::Stl);
+            let (mut source, format) = open_buf(Box::new(Cursor::new(stl)), &LoadOptions::default()).unwrap();
+            assert_eq!(format, Format::Stl);
+            assert_eq!(source.read_all().unwrap().len(), 1);
+        }
+
+        // text needs to be valid utf8
+        assert_eq!(Format::sniff(b"# \xff\xfe\x00\x01"), Format::Stl);
+        assert_eq!(Format::sniff(b"# caf\xc3\xa9\nv 0 0 0\n"), Format::Obj);
+        // cut within a character
+        assert_eq!(Format::sniff(b"# caf\xc3"), Format::Obj);
+    }
+
+    #[test]
+    fn sniff_zip_entries() {
+        let mut header = b"PK\x03\x04".to_vec();
+        header.resize(26, 0);
+        header.extend_from_slice(&[9, 0, 0, 0]);
+        header.extend_from_slice(b"model.AMF");
+        assert_eq!(Format::sniff(&header), Format::Amf);
+
+        header.truncate(30);
+        header[26] = 19;
+        header.extend_from_slice(b"[Content_Types].xml");
+        assert_eq!(Format::sniff(&header), Format::ThreeMf);
+    }
+
+    #[test]
+    fn open_by_content() {
+        let (mut source, format) = open_buf(Box::new(Cursor::new(TRI_BIN)), &LoadOptions::default()).unwrap();
+        assert_eq!(format, Format::Stl);
+        assert_eq!(source.triangle_count_hint(), Some(1));
+        assert_eq!(source.read_all().unwrap().len(), 1);
+
+        let off = "OFF\n3 1 0\n0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n";
+        let (mut source, format) = open_buf(Box::new(Cursor::new(off)), &LoadOptions::default()).unwrap();
+        assert_eq!(format, Format::Off);
+
+        // rewinding starts over
+        assert!(source.next_triangle().unwrap().is_some());
+        source.rewind().unwrap();
+        assert!(source.next_triangle().unwrap().is_some());
+        assert!(source.next_triangle().unwrap().is_none());
+
+        // the triangles read into memory are handed over
+        assert_eq!(source.read_all().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn open_streams() {
+        // STL is streamed
+        let (source, format) = open_stream(Box::new(TRI_BIN), &LoadOptions::default()).unwrap();
+        assert_eq!(format, Format::Stl);
+        assert!(!source.is_multi_pass());
+
+        let mut source = multi_pass(source);
+        assert_eq!(source.read_all().unwrap().len(), 1);
+        assert_eq!(source.read_all().unwrap().len(), 1);
+
+        // everything else is read into memory
+        let off: &[u8] = b"OFF\n3 1 0\n0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n";
+        let (mut source, format) = open_stream(Box::new(off), &LoadOptions::default()).unwrap();
+        assert_eq!(format, Format::Off);
+        assert!(source.is_multi_pass());
+        assert_eq!(source.read_all().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn open_stl_streams() {
+        // piped binary files whose header starts with 'solid' are streamed as binary
+        for triangles in &[2, 20] {
+            let mut stl = b"solid piped\n".to_vec();
+            stl.resize(80, b' ');
+            stl.extend_from_slice(&(*triangles as u32).to_le_bytes());
+            stl.resize(84 + 50 * triangles, 0);
+
+            let (mut source, _) = open_stream(Box::new(Cursor::new(stl)), &LoadOptions::default()).unwrap();
+            assert!(!source.is_multi_pass());
+            assert_eq!(source.read_all().unwrap().len(), *triangles);
+        }
+
+        // ascii files whose first line doesn't end within the sniffed bytes are read into memory
+        let mut stl = format!("solid {}\n", "x".repeat(600));
+        stl.push_str("facet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nvertex 0 1 0\n");
+        stl.push_str("endloop\nendfacet\nendsolid\n");
+
+        let (mut source, format) = open_stream(Box::new(Cursor::new(stl)), &LoadOptions::default()).unwrap();
+        assert_eq!(format, Format::Stl);
+        assert!(source.is_multi_pass());
+        assert_eq!(source.read_all().unwrap().len(), 1);
+    }
+}
@@ -1,22 +1,22 @@
-mod aabb;
-mod encoder;
-mod ffi;
-mod mesh;
-mod parser;
-mod picture;
-mod rasterbackend;
-mod zbuffer;
-
-use anyhow::Result;
-use encoder::*;
-use mesh::{LazyMesh, Mesh};
-use mesh::{Triangle, Vec3};
-use parser::Parser;
-use picture::Picture;
-use rasterbackend::RasterBackend;
-
-use clap::{App, Arg};
-use std::error::Error;
+use anyhow::{anyhow, Result};
+use stl2thumbnail::aabb::AABB;
+use stl2thumbnail::components::assign_components;
+use stl2thumbnail::decimate::{decimate, target_for_resolution};
+use stl2thumbnail::defects::Defects;
+use stl2thumbnail::encoder::*;
+use stl2thumbnail::loader::{self, Format, LoadOptions};
+use stl2thumbnail::mesh::{LazyMesh, MeshSource};
+use stl2thumbnail::mesh::{Triangle, Vec3};
+use stl2thumbnail::orientation::orient;
+use stl2thumbnail::picture::Picture;
+use stl2thumbnail::pose::{best_pose, detect_up_axis};
+use stl2thumbnail::rasterbackend::{RasterBackend, RenderOptions};
+use stl2thumbnail::smooth::{smooth_normals, DEFAULT_CREASE_ANGLE};
+use stl2thumbnail::stats::Stats;
+use stl2thumbnail::transform::{Axis, Transform, Transformed};
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::io;
 use std::time::{Duration, Instant};
 
 struct Settings {
@@ -26,6 +26,19 @@ struct Settings {
     turntable: bool,
     size_hint: bool,
     grid: bool,
+    color_parts: bool,
+    color_components: bool,
+    palette: Vec<Vec3>,
+    defects: bool,
+    two_sided: bool,
+    repair: bool,
+    decimate: Option<usize>,
+    smooth: bool,
+    crease_angle: f32,
+    transform: Transform,
+    /// detected if None ('auto')
+    up_axis: Option<Axis>,
+    auto_orient: bool,
     cam_elevation: f32,
     cam_azimuth: f32,
     timeout: Option<Duration>,
@@ -34,13 +47,40 @@ struct Settings {
 fn main() -> Result<()> {
     let matches = App::new("stl2thumbnail")
         .version(clap::crate_version!())
-        .about("Generates thumbnails from STL files")
+        .about("Generates thumbnails from STL, OBJ, 3MF, AMF, PLY, OFF and glTF files")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(
+            SubCommand::with_name("info")
+                .about("Prints the statistics of the model (volume, surface area, watertightness, ...)")
+                .arg(
+                    Arg::with_name("INPUT")
+                        .index(1)
+                        .help("Input filename, '-' reads from stdin")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("JSON")
+                        .long("json")
+                        .help("Prints the statistics as JSON"),
+                )
+                .arg(
+                    Arg::with_name("ENTRY")
+                        .long("entry")
+                        .takes_value(true)
+                        .help("Selects the model of a zip archive (defaults to the largest model file)"),
+                )
+                .arg(
+                    Arg::with_name("LENIENT")
+                        .long("lenient")
+                        .help("Reads what's left of malformed binary STL files instead of rejecting them"),
+                ),
+        )
         .arg(
             Arg::with_name("INPUT")
                 .short("i")
                 .index(1)
                 .long("input")
-                .help("Input filename")
+                .help("Input filename, '-' reads from stdin")
                 .required(true),
         )
         .arg(
@@ -51,6 +91,17 @@ fn main() -> Result<()> {
                 .help("Output filename")
                 .required(true),
         )
+        .arg(
+            Arg::with_name("ENTRY")
+                .long("entry")
+                .takes_value(true)
+                .help("Selects the model of a zip archive (defaults to the largest model file)"),
+        )
+        .arg(
+            Arg::with_name("LENIENT")
+                .long("lenient")
+                .help("Renders what's left of malformed binary STL files instead of rejecting them"),
+        )
         .arg(
             Arg::with_name("TURNTABLE")
                 .short("t")
@@ -64,6 +115,12 @@ fn main() -> Result<()> {
                 .long("lazy")
                 .help("Enables low memory usage mode"),
         )
+        .arg(
+            Arg::with_name("MMAP")
+                .short("m")
+                .long("mmap")
+                .help("Memory maps binary STL files, fast for huge files in low memory usage mode"),
+        )
         .arg(
             Arg::with_name("RECALC_NORMALS")
                 .short("n")
@@ -109,6 +166,92 @@ fn main() -> Result<()> {
                 .takes_value(true)
                 .help("Show or hide the grid"),
         )
+        .arg(
+            Arg::with_name("COLOR_PARTS")
+                .short("p")
+                .long("parts")
+                .help("Draws each part of the model in its own color"),
+        )
+        .arg(
+            Arg::with_name("COLOR_COMPONENTS")
+                .long("components")
+                .help("Draws each connected component of the model in its own color (keeps the whole model in memory)"),
+        )
+        .arg(
+            Arg::with_name("PALETTE")
+                .long("palette")
+                .takes_value(true)
+                .help("Colors of the parts and components, e.g. '0073FF,FF8000,33BF33'"),
+        )
+        .arg(
+            Arg::with_name("DEFECTS")
+                .long("defects")
+                .help("Highlights open and non-manifold edges and flipped faces (keeps the whole model in memory)"),
+        )
+        .arg(
+            Arg::with_name("TWO_SIDED")
+                .long("two-sided")
+                .help("Draws the back faces in their own color instead of culling them"),
+        )
+        .arg(
+            Arg::with_name("REPAIR")
+                .long("repair")
+                .help("Turns flipped faces and inside out models around (keeps the whole model in memory)"),
+        )
+        .arg(
+            Arg::with_name("DECIMATE")
+                .long("decimate")
+                .takes_value(true)
+                .help("Simplifies the model to the given triangle count before rendering, 'auto' derives it from the image size (keeps the whole model in memory)"),
+        )
+        .arg(
+            Arg::with_name("SMOOTH")
+                .short("s")
+                .long("smooth")
+                .help("Enables smooth shading (keeps the whole model in memory)"),
+        )
+        .arg(
+            Arg::with_name("CREASE_ANGLE")
+                .long("crease-angle")
+                .takes_value(true)
+                .help("Edges steeper than this angle stay sharp in smooth shading mode (defaults to 30°)"),
+        )
+        .arg(
+            Arg::with_name("UP_AXIS")
+                .long("up-axis")
+                .takes_value(true)
+                .possible_values(&["x", "y", "z", "auto"])
+                .help("The up axis of the model, 'auto' guesses it from the format and the geometry (defaults to z)"),
+        )
+        .arg(
+            Arg::with_name("AUTO_ORIENT")
+                .long("auto-orient")
+                .help("Turns the model onto its most stable face, the way it would be printed (keeps the whole model in memory)"),
+        )
+        .arg(
+            Arg::with_name("SCALE")
+                .long("scale")
+                .takes_value(true)
+                .help("Scales the model uniformly, e.g. '2', or per axis, e.g. 'x=2,z=0.5'"),
+        )
+        .arg(
+            Arg::with_name("MIRROR")
+                .long("mirror")
+                .takes_value(true)
+                .help("Mirrors the model along the given axes, e.g. 'y' or 'x,z'"),
+        )
+        .arg(
+            Arg::with_name("ROTATE")
+                .long("rotate")
+                .takes_value(true)
+                .help("Rotates the model by degrees around the given axes in order, e.g. 'x=90,z=45'"),
+        )
+        .arg(
+            Arg::with_name("TRANSLATE")
+                .long("translate")
+                .takes_value(true)
+                .help("Moves the model, e.g. 'x=10,y=-5' (transforms are applied in the order scale, mirror, rotate, translate)"),
+        )
         .arg(
             Arg::with_name("TIMEOUT")
                 .long("timeout")
@@ -117,8 +260,13 @@ fn main() -> Result<()> {
         )
         .get_matches();
 
+    if let Some(matches) = matches.subcommand_matches("info") {
+        return info(matches);
+    }
+
     let input = matches.value_of("INPUT").unwrap();
     let output = matches.value_of("OUTPUT").unwrap();
+    let entry = matches.value_of("ENTRY");
 
     let width = matches
         .value_of("WIDTH")
@@ -142,6 +290,38 @@ fn main() -> Result<()> {
             .unwrap_or_default()
             .parse::<bool>()
             .unwrap_or(true),
+        color_parts: matches.is_present("COLOR_PARTS"),
+        color_components: matches.is_present("COLOR_COMPONENTS"),
+        palette: match matches.value_of("PALETTE") {
+            Some(palette) => RenderOptions::parse_palette(palette)?,
+            None => RenderOptions::default_part_colors(),
+        },
+        defects: matches.is_present("DEFECTS"),
+        two_sided: matches.is_present("TWO_SIDED"),
+        repair: matches.is_present("REPAIR"),
+        decimate: match matches.value_of("DECIMATE") {
+            None => None,
+            Some("auto") => Some(target_for_resolution(width, height)),
+            Some(d) => Some(
+                d.parse::<usize>()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| anyhow!("invalid triangle count '{}', expected a positive number or 'auto'", d))?,
+            ),
+        },
+        smooth: matches.is_present("SMOOTH"),
+        crease_angle: matches
+            .value_of("CREASE_ANGLE")
+            .unwrap_or_default()
+            .parse::<f32>()
+            .unwrap_or(DEFAULT_CREASE_ANGLE),
+        transform: parse_transform(&matches)?,
+        up_axis: match matches.value_of("UP_AXIS") {
+            None => Some(Axis::Z),
+            Some("auto") => None,
+            Some(axis) => Some(axis.parse()?),
+        },
+        auto_orient: matches.is_present("AUTO_ORIENT"),
         cam_elevation: matches
             .value_of("CAM_ELEVATION")
             .unwrap_or_default()
@@ -157,31 +337,131 @@ fn main() -> Result<()> {
             .unwrap_or_default()
             .parse::<u64>()
             .ok()
-            .map_or(None, |v| Some(Duration::from_millis(v))),
+            .map(Duration::from_millis),
     };
 
     if settings.verbose {
         println!("Size                  '{}x{}'", width, height);
         println!("Input                 '{}'", input);
         println!("Output                '{}'", output);
+        if let Some(entry) = entry {
+            println!("Archive entry         '{}'", entry);
+        }
         println!("Recalculate normals   '{}'", settings.recalculate_normals);
         println!("Low memory usage mode '{}'", settings.lazy);
         println!("Draw dimensions       '{}'", settings.size_hint);
         println!("Grid visible          '{}'", settings.grid);
+        println!("Color parts           '{}'", settings.color_parts);
+        println!("Color components      '{}'", settings.color_components);
+        println!("Highlight defects     '{}'", settings.defects);
+        println!("Two-sided lighting    '{}'", settings.two_sided);
+        println!("Repair orientation    '{}'", settings.repair);
+        println!("Decimate              {:?}", settings.decimate);
+        println!("Smooth shading        '{}'", settings.smooth);
+        println!("Crease angle          {}°", settings.crease_angle);
         println!("Cam elevation         {}°", settings.cam_elevation);
         println!("Cam azimuth           {}°", settings.cam_azimuth);
         println!("Timeout               {:?}", settings.timeout);
     }
 
     let start_time = Instant::now();
-    let mut parser = Parser::from_file(&input, settings.recalculate_normals)?;
 
-    if settings.lazy {
-        let parsed_mesh = LazyMesh::new(&mut parser);
-        create(width, height, &parsed_mesh, &output, &settings)?;
+    let options = LoadOptions {
+        recalculate_normals: settings.recalculate_normals,
+        entry: entry.map(|e| e.to_string()),
+        lenient: matches.is_present("LENIENT"),
+        memory_map: matches.is_present("MMAP"),
+    };
+
+    let (mut source, format) = open(input, &options)?;
+
+    // smooth shading, defect detection, repairs, decimation and components need the adjacency of the whole model,
+    // the auto orientation needs all of its vertices
+    if settings.lazy
+        && !settings.smooth
+        && !settings.defects
+        && !settings.repair
+        && settings.decimate.is_none()
+        && !settings.color_components
+        && !settings.auto_orient
+    {
+        // the renderer iterates the model several times, streams are buffered
+        source = loader::multi_pass(source);
+
+        let parsed_mesh = LazyMesh::new(source.as_mut());
+        let mesh = Transformed::new(&parsed_mesh, model_transform(&parsed_mesh, format, &settings));
+        let aabb = AABB::from_iterable(mesh);
+
+        // the renderer can't report errors, the bounds have read the whole model already
+        if let Some(e) = parsed_mesh.take_error() {
+            return Err(e.into());
+        }
+        print_warnings(&parsed_mesh.warnings());
+
+        create(width, height, mesh, &aabb, None, output, &settings)?;
+        if let Some(e) = parsed_mesh.take_error() {
+            return Err(e.into());
+        }
     } else {
-        let parsed_mesh = parser.read_all()?;
-        create(width, height, &parsed_mesh, &output, &settings)?;
+        let (mut parsed_mesh, mut aabb) = source.read_all_with_bounds()?;
+        print_warnings(source.warnings());
+
+        // inverted faces are repaired unless they should be highlighted, the auto orientation trusts the winding
+        if settings.repair && !settings.defects {
+            let orientation = orient(&mut parsed_mesh);
+            if settings.verbose && !orientation.is_empty() {
+                println!(
+                    "Repaired orientation  {} inconsistent normals, {} flipped faces, inverted '{}'",
+                    orientation.inconsistent_normals, orientation.flipped_faces, orientation.inverted
+                );
+            }
+        }
+
+        let transform = model_transform(&parsed_mesh, format, &settings);
+        if !transform.is_identity() {
+            transform.apply_mesh(&mut parsed_mesh);
+            aabb = AABB::from_iterable(&parsed_mesh);
+        }
+
+        // the defects are found in the simplified model such that the overlay matches the rendered geometry
+        if let Some(target) = settings.decimate {
+            let triangles = parsed_mesh.len();
+            parsed_mesh = decimate(&parsed_mesh, target);
+            // collapsed edges are moved to the position minimizing the error, which might lie outside of the bounds
+            aabb = AABB::from_mesh(&parsed_mesh);
+            if settings.verbose {
+                println!("Decimated             {} to {} triangles", triangles, parsed_mesh.len());
+            }
+        }
+
+        let defects = if settings.defects {
+            let defects = Defects::from_mesh(&parsed_mesh);
+            defects.tint(&mut parsed_mesh, RenderOptions::default().flipped_face_color);
+            if settings.verbose {
+                println!(
+                    "Defects               {} open edges, {} non-manifold edges, {} flipped faces",
+                    defects.open_edges.len(),
+                    defects.non_manifold_edges.len(),
+                    defects.flipped_faces.len()
+                );
+            }
+            Some(defects)
+        } else {
+            None
+        };
+
+        if settings.color_components {
+            let components = assign_components(&mut parsed_mesh);
+            if settings.verbose {
+                println!("Components            {}", components.len());
+            }
+        }
+
+        if settings.smooth {
+            smooth_normals(&mut parsed_mesh, settings.crease_angle);
+        }
+
+        create(width, height, &parsed_mesh, &aabb, defects.as_ref(), output, &settings)?;
     }
 
     if settings.verbose {
@@ -195,17 +475,102 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+fn open(input: &str, options: &LoadOptions) -> Result<(Box<dyn MeshSource>, Format)> {
+    if input == "-" {
+        loader::open_stream(Box::new(io::stdin()), options)
+    } else {
+        loader::open(input, options)
+    }
+}
+
+fn info(matches: &ArgMatches) -> Result<()> {
+    let options = LoadOptions {
+        entry: matches.value_of("ENTRY").map(|e| e.to_string()),
+        lenient: matches.is_present("LENIENT"),
+        ..LoadOptions::default()
+    };
+
+    let (mut source, _) = open(matches.value_of("INPUT").unwrap(), &options)?;
+    let mesh = source.read_all()?;
+    print_warnings(source.warnings());
+
+    let stats = Stats::from_mesh(&mesh);
+    if matches.is_present("JSON") {
+        println!("{}", serde_json::to_string_pretty(&stats.to_json())?);
+    } else {
+        println!("{}", stats);
+    }
+
+    Ok(())
+}
+
+// rotates the up axis of the model onto the z axis and the model onto its most stable face if requested,
+// followed by the transforms given on the command line
+fn model_transform(mesh: impl IntoIterator<Item = Triangle> + Copy, format: Format, settings: &Settings) -> Transform {
+    let up_axis = settings.up_axis.unwrap_or_else(|| detect_up_axis(mesh, Some(format)));
+
+    if settings.verbose {
+        println!("Up axis               {:?}", up_axis);
+    }
+
+    let mut transform = Transform::up_axis(up_axis);
+    if settings.auto_orient {
+        if let Some(pose) = best_pose(Transformed::new(mesh, transform)) {
+            if settings.verbose {
+                println!(
+                    "Pose                  contact {:.1}, overhangs {:.1}, tips over at {:.1}°",
+                    pose.contact_area,
+                    pose.overhang_area,
+                    pose.stability.to_degrees()
+                );
+            }
+            transform = transform.then(&pose.transform());
+        }
+    }
+
+    transform.then(&settings.transform)
+}
+
+// the transforms given on the command line in the order scale, mirror, rotate, translate
+fn parse_transform(matches: &ArgMatches) -> Result<Transform> {
+    let mut transform = Transform::default();
+
+    if let Some(spec) = matches.value_of("SCALE") {
+        transform = transform.then(&Transform::parse_scaling(spec)?);
+    }
+    if let Some(spec) = matches.value_of("MIRROR") {
+        transform = transform.then(&Transform::parse_mirror(spec)?);
+    }
+    if let Some(spec) = matches.value_of("ROTATE") {
+        transform = transform.then(&Transform::parse_rotation(spec)?);
+    }
+    if let Some(spec) = matches.value_of("TRANSLATE") {
+        transform = transform.then(&Transform::parse_translation(spec)?);
+    }
+
+    Ok(transform)
+}
+
+// streams report problems once they have been read
+fn print_warnings(warnings: &[String]) {
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
+}
+
 fn create(
     width: u32,
     height: u32,
     mesh: impl IntoIterator<Item = Triangle> + Copy,
+    aabb: &AABB,
+    defects: Option<&Defects>,
     path: &str,
     settings: &Settings,
 ) -> Result<()> {
     if settings.turntable {
-        create_turntable_animation(width, height, mesh, path, settings)
+        create_turntable_animation(width, height, mesh, aabb, defects, path, settings)
     } else {
-        create_still(width, height, mesh, path, settings)
+        create_still(width, height, mesh, aabb, defects, path, settings)
     }
 }
 
@@ -213,12 +578,18 @@ fn create_still(
     width: u32,
     height: u32,
     mesh: impl IntoIterator<Item = Triangle> + Copy,
+    aabb: &AABB,
+    defects: Option<&Defects>,
     path: &str,
     settings: &Settings,
 ) -> Result<()> {
-    let elevation_angle = settings.cam_elevation * std::f32::consts::PI / 180.0;
     let mut backend = RasterBackend::new(width, height);
     backend.render_options.grid_visible = settings.grid;
+    backend.render_options.two_sided = settings.two_sided;
+    // part colors would hide the flipped faces
+    if (settings.color_parts || settings.color_components) && defects.is_none() {
+        backend.render_options.part_colors = settings.palette.clone();
+    }
 
     backend.render_options.view_pos = Vec3::new(
         settings.cam_azimuth.to_radians().cos(),
@@ -226,11 +597,15 @@ fn create_still(
         -settings.cam_elevation.to_radians().tan(),
     );
 
-    let (aabb, scale) = backend.fit_mesh_scale(mesh);
+    let scale = backend.fit_aabb_scale(aabb);
     backend.render_options.zoom = 1.05;
     backend.render_options.draw_size_hint = settings.size_hint;
 
-    backend.render(mesh, scale, &aabb, settings.timeout).save(path)?;
+    let mut pic = backend.render(mesh, scale, aabb, settings.timeout);
+    if let Some(defects) = defects {
+        backend.draw_defects(&mut pic, defects, scale, aabb);
+    }
+    pic.save(path)?;
 
     Ok(())
 }
@@ -239,15 +614,22 @@ fn create_turntable_animation(
     width: u32,
     height: u32,
     mesh: impl IntoIterator<Item = Triangle> + Copy,
+    aabb: &AABB,
+    defects: Option<&Defects>,
     path: &str,
     settings: &Settings,
 ) -> Result<()> {
     let mut backend = RasterBackend::new(width, height);
     backend.render_options.grid_visible = settings.grid;
+    backend.render_options.two_sided = settings.two_sided;
+    // part colors would hide the flipped faces
+    if (settings.color_parts || settings.color_components) && defects.is_none() {
+        backend.render_options.part_colors = settings.palette.clone();
+    }
     let mut pictures: Vec<Picture> = Vec::new();
 
     backend.render_options.view_pos = Vec3::new(1.0, 1.0, -settings.cam_elevation.to_radians().tan());
-    let (aabb, scale) = backend.fit_mesh_scale(mesh);
+    let scale = backend.fit_aabb_scale(aabb);
     backend.render_options.zoom = 1.05;
     backend.render_options.draw_size_hint = settings.size_hint;
 
@@ -255,7 +637,11 @@ fn create_turntable_animation(
         let angle = (8.0 * i as f32).to_radians();
         backend.render_options.view_pos =
             Vec3::new(angle.cos(), angle.sin(), -settings.cam_elevation.to_radians().tan());
-        pictures.push(backend.render(mesh, scale, &aabb, settings.timeout));
+        let mut pic = backend.render(mesh, scale, aabb, settings.timeout);
+        if let Some(defects) = defects {
+            backend.draw_defects(&mut pic, defects, scale, aabb);
+        }
+        pictures.push(pic);
     }
 
     encode_gif(path, pictures.as_slice())?;
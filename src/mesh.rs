@@ -1,7 +1,10 @@
-use crate::parser::Parser;
+use crate::aabb::AABB;
+use crate::parser::ParseError;
+use anyhow::{bail, Result};
 use std::cell::RefCell;
-use std::io::{Read, Seek};
-use std::ops::Index;
+use std::io;
+use std::ops::{Index, IndexMut};
+use std::sync::Arc;
 
 // glm aliases
 pub type Mat4 = glm::Mat4x4;
@@ -14,16 +17,95 @@ pub fn matmul(m: &Mat4, v: &Vec3) -> Vec3 {
     (m * Vec4::new(v.x, v.y, v.z, 1.0)).xyz()
 }
 
+/// Optional attributes of a triangle
+/// Most triangles (e.g. the ones of STL files) have none, hence they are stored out of line and
+/// shared by the copies of a triangle.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Attributes {
+    /// color defined by the model file
+    pub color: Option<Vec3>,
+    /// per vertex colors defined by the model file, take precedence over the color of the triangle
+    pub colors: Option<[Vec3; 3]>,
+    /// per vertex normals for smooth shading, the normal is used for flat shading otherwise
+    pub normals: Option<[Vec3; 3]>,
+}
+
 // Triangle
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Triangle {
     pub vertices: [Vec3; 3],
     pub normal: Vec3,
+    /// index of the part (e.g. ascii 'solid') the triangle belongs to
+    pub part: u32,
+    pub attributes: Option<Arc<Attributes>>,
 }
 
 impl Triangle {
     pub fn new(vertices: [Vec3; 3], normal: Vec3) -> Self {
-        Self { vertices, normal }
+        Self {
+            vertices,
+            normal,
+            part: 0,
+            attributes: None,
+        }
+    }
+
+    pub fn color(&self) -> Option<Vec3> {
+        self.attributes.as_ref().and_then(|a| a.color)
+    }
+
+    pub fn vertex_colors(&self) -> Option<[Vec3; 3]> {
+        self.attributes.as_ref().and_then(|a| a.colors)
+    }
+
+    pub fn vertex_normals(&self) -> Option<[Vec3; 3]> {
+        self.attributes.as_ref().and_then(|a| a.normals)
+    }
+
+    pub fn set_color(&mut self, color: Option<Vec3>) {
+        self.update_attributes(|a| a.color = color);
+    }
+
+    pub fn set_vertex_colors(&mut self, colors: Option<[Vec3; 3]>) {
+        self.update_attributes(|a| a.colors = colors);
+    }
+
+    pub fn set_vertex_normals(&mut self, normals: Option<[Vec3; 3]>) {
+        self.update_attributes(|a| a.normals = normals);
+    }
+
+    // copies shared attributes on write and drops empty ones
+    fn update_attributes(&mut self, update: impl FnOnce(&mut Attributes)) {
+        let mut attributes = self.attributes.as_deref().copied().unwrap_or_default();
+        update(&mut attributes);
+
+        self.attributes = if attributes == Attributes::default() {
+            None
+        } else {
+            Some(Arc::new(attributes))
+        };
+    }
+
+    /// Vertices with NaN or infinite coordinates can't be rendered
+    pub fn has_finite_vertices(&self) -> bool {
+        self.vertices.iter().flat_map(|v| v.iter()).all(|c| c.is_finite())
+    }
+
+    /// Calculates the normal from the vertices using the right hand rule
+    pub fn recalculate_normal(&mut self) {
+        self.normal = (self.vertices[1] - self.vertices[0])
+            .cross(&(self.vertices[2] - self.vertices[0]))
+            .normalize();
+    }
+
+    /// Turns the triangle around by reversing its winding and normals
+    pub fn flip(&mut self) {
+        self.vertices.swap(1, 2);
+        self.normal = -self.normal;
+        if self.attributes.is_some() {
+            self.set_vertex_colors(self.vertex_colors().map(|[a, b, c]| [a, c, b]));
+            self.set_vertex_normals(self.vertex_normals().map(|[a, b, c]| [-a, -c, -b]));
+        }
     }
 }
 
@@ -32,7 +114,7 @@ pub struct Mesh(Vec<Triangle>);
 
 impl Mesh {
     pub fn new(data: Vec<Triangle>) -> Self {
-        Self { 0: data }
+        Self(data)
     }
 }
 
@@ -40,6 +122,43 @@ impl Mesh {
     pub fn len(&self) -> usize {
         self.0.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn push(&mut self, triangle: Triangle) {
+        self.0.push(triangle);
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, Triangle> {
+        self.0.iter_mut()
+    }
+
+    /// Groups the triangles by their part index
+    /// Parts without a name (e.g. after assigning components) are added unnamed.
+    pub fn split_parts(&self, names: &[String]) -> Vec<Part> {
+        let mut parts: Vec<Part> = names
+            .iter()
+            .map(|name| Part {
+                name: name.clone(),
+                mesh: Mesh::new(vec![]),
+            })
+            .collect();
+
+        for t in self {
+            let part = t.part as usize;
+            if part >= parts.len() {
+                parts.resize_with(part + 1, || Part {
+                    name: String::new(),
+                    mesh: Mesh::new(vec![]),
+                });
+            }
+            parts[part].mesh.push(t);
+        }
+
+        parts
+    }
 }
 
 impl Index<usize> for Mesh {
@@ -49,6 +168,18 @@ impl Index<usize> for Mesh {
     }
 }
 
+impl IndexMut<usize> for Mesh {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.0[index]
+    }
+}
+
+// Part
+pub struct Part {
+    pub name: String,
+    pub mesh: Mesh,
+}
+
 pub struct MeshIter<'a> {
     mesh: &'a [Triangle],
     i: usize,
@@ -70,46 +201,278 @@ impl<'a> Iterator for MeshIter<'a> {
     type Item = Triangle;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let triangle = self.mesh.get(self.i).copied();
+        let triangle = self.mesh.get(self.i).cloned();
         self.i += 1;
         triangle
     }
 }
 
+// MeshSource
+/// A reader producing the triangles of a model one by one
+pub trait MeshSource {
+    /// Starts over at the first triangle
+    fn rewind(&mut self) -> Result<()>;
+
+    /// Returns the next triangle or None at the end of the model
+    fn next_triangle(&mut self) -> Result<Option<Triangle>, ParseError>;
+
+    /// Number of triangles if it is known without reading the whole model
+    fn triangle_count_hint(&self) -> Option<u64>;
+
+    /// Whether the source can be rewound and read multiple times
+    fn is_multi_pass(&self) -> bool {
+        true
+    }
+
+    /// Problems the reader recovered from, e.g. in lenient mode
+    fn warnings(&self) -> &[String] {
+        &[]
+    }
+
+    /// Names of the parts of the model, empty if the format has no notion of parts
+    fn part_names(&self) -> &[String] {
+        &[]
+    }
+
+    /// Reads the whole model, readers holding the model in memory hand it over instead of copying it
+    fn read_all(&mut self) -> Result<Mesh> {
+        self.rewind()?;
+        let mut triangles = Vec::with_capacity(self.triangle_count_hint().unwrap_or(0).min(1 << 20) as usize);
+
+        while let Some(triangle) = self.next_triangle()? {
+            triangles.push(triangle);
+        }
+
+        Ok(Mesh::new(triangles))
+    }
+
+    /// Reads the whole model and computes its AABB
+    fn read_all_with_bounds(&mut self) -> Result<(Mesh, AABB)> {
+        let mesh = self.read_all()?;
+        let aabb = AABB::from_mesh(&mesh);
+        Ok((mesh, aabb))
+    }
+}
+
+// InMemorySource
+/// Source for formats which are parsed as a whole, the triangles are iterated from memory
+/// read_all hands the triangles over without copying them, reading the source again fails afterwards.
+pub struct InMemorySource {
+    triangles: Vec<Triangle>,
+    parts: Vec<String>,
+    next: usize,
+    handed_over: bool,
+}
+
+impl InMemorySource {
+    pub fn new(triangles: Vec<Triangle>, parts: Vec<String>) -> Self {
+        Self {
+            triangles,
+            parts,
+            next: 0,
+            handed_over: false,
+        }
+    }
+
+    pub fn triangle_count(&self) -> u64 {
+        self.triangles.len() as u64
+    }
+
+    /// Reads the whole model and splits it into its parts
+    pub fn read_parts(&mut self) -> Result<Vec<Part>> {
+        Ok(self.read_all()?.split_parts(&self.parts))
+    }
+}
+
+impl MeshSource for InMemorySource {
+    fn rewind(&mut self) -> Result<()> {
+        if self.handed_over {
+            bail!("the triangles have already been read as a whole, the model can't be read again");
+        }
+        self.next = 0;
+        Ok(())
+    }
+
+    fn next_triangle(&mut self) -> Result<Option<Triangle>, ParseError> {
+        let triangle = self.triangles.get(self.next).cloned();
+        if triangle.is_some() {
+            self.next += 1;
+        }
+        Ok(triangle)
+    }
+
+    fn triangle_count_hint(&self) -> Option<u64> {
+        Some(self.triangle_count())
+    }
+
+    fn part_names(&self) -> &[String] {
+        &self.parts
+    }
+
+    fn read_all(&mut self) -> Result<Mesh> {
+        self.rewind()?;
+        self.handed_over = true;
+        Ok(Mesh::new(std::mem::take(&mut self.triangles)))
+    }
+}
+
 // LazyMesh
-pub struct LazyMesh<'a, T: Read + Seek> {
-    parser: RefCell<&'a mut Parser<T>>, // inner mutability
+/// Streams the triangles from a multi-pass source on every iteration
+/// Iterating stops at the first error, which is kept until it is taken. Single-pass sources which
+/// can't be rewound yield nothing after the first iteration, buffer them to read them again.
+pub struct LazyMesh<'a> {
+    source: RefCell<&'a mut dyn MeshSource>, // inner mutability
+    error: RefCell<Option<ParseError>>,
 }
 
-impl<'a, T> LazyMesh<'a, T>
-where
-    T: Read + Seek,
-{
-    pub fn new(parser: &'a mut Parser<T>) -> Self {
+impl<'a> LazyMesh<'a> {
+    pub fn new(source: &'a mut dyn MeshSource) -> Self {
         Self {
-            parser: RefCell::new(parser),
+            source: RefCell::new(source),
+            error: RefCell::new(None),
         }
     }
+
+    /// Problems the source recovered from so far
+    pub fn warnings(&self) -> Vec<String> {
+        self.source.borrow().warnings().to_vec()
+    }
+
+    /// Returns the first error encountered while iterating
+    pub fn take_error(&self) -> Option<ParseError> {
+        self.error.borrow_mut().take()
+    }
 }
 
-pub struct LazyMeshIter<'a, T: Read + Seek> {
-    parser: &'a RefCell<&'a mut Parser<T>>,
+pub struct LazyMeshIter<'a> {
+    mesh: &'a LazyMesh<'a>,
+    done: bool,
 }
 
-impl<'a, 'b, T: Read + Seek> IntoIterator for &'a LazyMesh<'a, T> {
+impl<'a> IntoIterator for &'a LazyMesh<'a> {
     type Item = Triangle;
-    type IntoIter = LazyMeshIter<'a, T>;
+    type IntoIter = LazyMeshIter<'a>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.parser.borrow_mut().rewind().unwrap();
-        Self::IntoIter { parser: &self.parser }
+        let done = match self.source.borrow_mut().rewind() {
+            Ok(()) => false,
+            Err(e) => {
+                self.error.borrow_mut().get_or_insert(io::Error::other(e).into());
+                true
+            }
+        };
+        Self::IntoIter { mesh: self, done }
     }
 }
 
-impl<'a, T: Read + Seek> Iterator for LazyMeshIter<'a, T> {
+impl<'a> Iterator for LazyMeshIter<'a> {
     type Item = Triangle;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.parser.borrow_mut().next_triangle()
+        if self.done {
+            return None;
+        }
+
+        match self.mesh.source.borrow_mut().next_triangle() {
+            Ok(triangle) => triangle,
+            Err(e) => {
+                self.mesh.error.borrow_mut().get_or_insert(e);
+                None
+            }
+        }
+    }
+}
+
+// axis aligned box between the given corners, wound counterclockwise seen from outside
+#[cfg(test)]
+pub(crate) fn cuboid(min: Vec3, max: Vec3) -> Mesh {
+    let corner = |i: usize| {
+        Vec3::new(
+            if i & 1 != 0 { max.x } else { min.x },
+            if i & 2 != 0 { max.y } else { min.y },
+            if i & 4 != 0 { max.z } else { min.z },
+        )
+    };
+    let quads = [
+        [0, 2, 3, 1],
+        [4, 5, 7, 6],
+        [0, 1, 5, 4],
+        [2, 6, 7, 3],
+        [0, 4, 6, 2],
+        [1, 3, 7, 5],
+    ];
+
+    let mut triangles = vec![];
+    for q in &quads {
+        for [a, b, c] in [[q[0], q[1], q[2]], [q[0], q[2], q[3]]] {
+            let mut triangle = Triangle::new([corner(a), corner(b), corner(c)], Vec3::new(0.0, 0.0, 0.0));
+            triangle.recalculate_normal();
+            triangles.push(triangle);
+        }
+    }
+
+    Mesh::new(triangles)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mesh::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn triangle_size() {
+        // plain triangles don't pay for colors and per vertex attributes
+        assert!(std::mem::size_of::<Triangle>() <= 64);
+    }
+
+    #[test]
+    fn vertex_attributes() {
+        let mut triangle = Triangle::new([Vec3::zeros(); 3], Vec3::new(0.0, 0.0, 1.0));
+        let colors = [Vec3::zeros(), Vec3::repeat(0.5), Vec3::repeat(1.0)];
+        triangle.set_vertex_colors(Some(colors));
+
+        // copies share the attributes until they are changed
+        let mut copy = triangle.clone();
+        assert!(Arc::ptr_eq(
+            triangle.attributes.as_ref().unwrap(),
+            copy.attributes.as_ref().unwrap()
+        ));
+        copy.flip();
+        assert_eq!(triangle.vertex_colors(), Some(colors));
+        assert_eq!(copy.vertex_colors(), Some([colors[0], colors[2], colors[1]]));
+
+        copy.set_vertex_colors(None);
+        assert!(copy.attributes.is_none());
+    }
+
+    #[test]
+    fn split_parts() {
+        let mut triangles = vec![Triangle::new([Vec3::zeros(); 3], Vec3::new(0.0, 0.0, 1.0)); 3];
+        triangles[1].part = 1;
+        triangles[2].part = 3;
+
+        // more parts than names
+        let parts = Mesh::new(triangles).split_parts(&["a".to_string(), "b".to_string()]);
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts.iter().map(|p| p.mesh.len()).collect::<Vec<_>>(), vec![1, 1, 0, 1]);
+        assert_eq!(parts[1].name, "b");
+        assert_eq!(parts[3].name, "");
+    }
+
+    #[test]
+    fn in_memory_source() {
+        let triangles = vec![Triangle::new([Vec3::zeros(); 3], Vec3::new(0.0, 0.0, 1.0)); 2];
+        let mut source = InMemorySource::new(triangles, vec!["a".to_string()]);
+
+        // iterating keeps the triangles
+        for _ in 0..2 {
+            source.rewind().unwrap();
+            assert_eq!(LazyMesh::new(&mut source).into_iter().count(), 2);
+        }
+
+        // reading them as a whole hands them over, later reads fail instead of returning nothing
+        assert_eq!(source.read_parts().unwrap()[0].mesh.len(), 2);
+        assert!(source.read_all().is_err());
+        assert!(source.rewind().is_err());
     }
 }
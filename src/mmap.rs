@@ -0,0 +1,146 @@
+use crate::aabb::AABB;
+use crate::compression::Compression;
+use crate::loader::Format;
+use crate::mesh::*;
+use crate::parallel::decode_binary;
+use crate::parser::{
+    check_triangle, decode_triangle, deduce_stl_type, BinaryLayout, ParseError, Recovery, StlType, HEADER_SIZE,
+    TRIANGLE_SIZE,
+};
+use anyhow::Result;
+use memmap2::Mmap;
+use std::fs;
+use std::io::Cursor;
+
+/// Binary STL reader operating on a memory mapped file
+/// The triangles are decoded directly from the mapped bytes, hence iterating
+/// the mesh multiple times is cheap and doesn't require loading it into memory.
+pub struct MappedParser {
+    map: Mmap,
+    layout: BinaryLayout,
+    recalculate_normals: bool,
+    triangle: u64,
+}
+
+impl MappedParser {
+    /// Maps the file, returns None if it isn't an uncompressed binary STL file
+    pub fn from_file(filename: &str, recalculate_normals: bool, recovery: Recovery) -> Result<Option<Self>> {
+        let file = fs::File::open(filename)?;
+        if file.metadata()?.len() < HEADER_SIZE + 4 {
+            return Ok(None);
+        }
+
+        // the file must not be modified while it is mapped
+        let map = unsafe { Mmap::map(&file)? };
+
+        if Compression::sniff(&map) != Compression::None
+            || Format::sniff_with_size(&map[..map.len().min(512)], map.len() as u64) != Format::Stl
+        {
+            return Ok(None);
+        }
+        if let StlType::Ascii = deduce_stl_type(&mut Cursor::new(&map[..]))? {
+            return Ok(None);
+        }
+
+        let layout = BinaryLayout::new(&map, map.len() as u64, recovery)?;
+
+        Ok(Some(Self {
+            map,
+            layout,
+            recalculate_normals,
+            triangle: 0,
+        }))
+    }
+
+    pub fn triangle_count(&self) -> u64 {
+        self.layout.triangles
+    }
+
+    /// Decodes the triangle at the given index
+    pub fn triangle(&self, index: u64) -> Result<Triangle, ParseError> {
+        if index >= self.layout.triangles {
+            return Err(ParseError::Truncated {
+                triangles: self.layout.triangles,
+            });
+        }
+
+        let offset = (HEADER_SIZE + 4 + index * TRIANGLE_SIZE) as usize;
+        let bytes = &self.map[offset..offset + TRIANGLE_SIZE as usize];
+
+        let mut triangle = decode_triangle(bytes, self.layout.facet_colors);
+        check_triangle(&mut triangle, index, self.recalculate_normals)?;
+
+        Ok(triangle)
+    }
+}
+
+impl MeshSource for MappedParser {
+    fn rewind(&mut self) -> Result<()> {
+        self.triangle = 0;
+        Ok(())
+    }
+
+    fn next_triangle(&mut self) -> Result<Option<Triangle>, ParseError> {
+        if self.triangle == self.layout.triangles {
+            return Ok(None);
+        }
+
+        let triangle = self.triangle(self.triangle)?;
+        self.triangle += 1;
+
+        Ok(Some(triangle))
+    }
+
+    fn triangle_count_hint(&self) -> Option<u64> {
+        Some(self.layout.triangles)
+    }
+
+    fn warnings(&self) -> &[String] {
+        &self.layout.warnings
+    }
+
+    fn read_all(&mut self) -> Result<Mesh> {
+        Ok(self.read_all_with_bounds()?.0)
+    }
+
+    // the triangles are decoded in parallel straight from the mapped file
+    fn read_all_with_bounds(&mut self) -> Result<(Mesh, AABB)> {
+        let start = (HEADER_SIZE + 4) as usize;
+        let end = start + (self.layout.triangles * TRIANGLE_SIZE) as usize;
+
+        Ok(decode_binary(
+            &self.map[start..end],
+            self.layout.facet_colors,
+            self.recalculate_normals,
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mesh::*;
+    use crate::mmap::MappedParser;
+    use crate::parser::Recovery;
+
+    #[test]
+    fn mapped_bin() {
+        let mut parser = MappedParser::from_file("src/test_models/triangle.stl", false, Recovery::Strict)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(parser.triangle_count(), 1);
+        assert_eq!(parser.triangle(0).unwrap().vertices[2], Vec3::new(0.0, 1.0, 0.0));
+
+        // iterating twice yields the same triangles
+        let first = parser.read_all().unwrap();
+        let second = parser.read_all().unwrap();
+        assert_eq!(first[0], second[0]);
+        assert_eq!(first[0].normal, Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn mapped_ascii() {
+        let parser = MappedParser::from_file("src/test_models/triangle_ascii.stl", false, Recovery::Strict).unwrap();
+        assert!(parser.is_none());
+    }
+}
@@ -0,0 +1,287 @@
+use crate::mesh::*;
+use crate::parser::{parse_vec3, tokenize, AsciiError, ParseError};
+use anyhow::{Error, Result};
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+
+// a face corner referencing a position and optionally a normal (0-based)
+#[derive(Debug, Clone, Copy)]
+struct Corner {
+    position: usize,
+    normal: Option<usize>,
+}
+
+/// Wavefront OBJ reader
+/// The indices of an OBJ file reference all previously declared vertices,
+/// hence the file is parsed as a whole. Groups and objects become parts.
+pub struct ObjParser;
+
+impl ObjParser {
+    pub fn from_buf<T: Read>(inner: T, recalculate_normals: bool) -> Result<InMemorySource> {
+        let mut reader = BufReader::new(inner);
+
+        let mut positions: Vec<Vec3> = vec![];
+        let mut normals: Vec<Vec3> = vec![];
+        let mut triangles = vec![];
+        let mut parts: Vec<String> = vec![];
+        let mut part_name = String::new();
+        let mut part = None;
+
+        let mut line_number = 0;
+        let mut buf = vec![];
+        let mut line = vec![];
+
+        loop {
+            buf.clear();
+            let eof = reader.read_until(b'\n', &mut buf)? == 0;
+            if eof && line.is_empty() {
+                break;
+            }
+            if !eof {
+                line_number += 1;
+            }
+
+            // lines ending with a backslash continue on the next line
+            let mut content = &buf[..];
+            while let [rest @ .., b'\r' | b'\n'] = content {
+                content = rest;
+            }
+            if let [content @ .., b'\\'] = content {
+                line.extend_from_slice(content);
+                line.push(b' ');
+                continue;
+            }
+            line.extend_from_slice(content);
+
+            // strip comments
+            let statement = match line.iter().position(|b| *b == b'#') {
+                Some(i) => &line[..i],
+                None => &line[..],
+            };
+
+            // exporters write names and materials in all kinds of encodings, the numbers are plain ascii
+            let statement = String::from_utf8_lossy(statement);
+            let tokens = tokenize(&statement);
+            let error = |column: usize, message: String| -> Error {
+                AsciiError {
+                    line: line_number,
+                    column,
+                    message,
+                }
+                .into()
+            };
+
+            match tokens.first().map(|(_, t)| *t) {
+                Some("v") => positions.push(parse_vec3(&tokens, 1).map_err(|(c, m)| error(c, m))?),
+                Some("vn") => normals.push(parse_vec3(&tokens, 1).map_err(|(c, m)| error(c, m))?),
+                Some("f") => {
+                    if tokens.len() < 4 {
+                        return Err(error(1, "a face requires at least 3 vertices".to_string()));
+                    }
+
+                    let corners = tokens[1..]
+                        .iter()
+                        .map(|(column, token)| {
+                            parse_corner(token, positions.len(), normals.len())
+                                .ok_or_else(|| error(*column, format!("invalid index '{}'", token)))
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+
+                    // parts are created on first use such that empty groups are skipped
+                    let index = match part {
+                        Some(index) => index,
+                        None => {
+                            let index = parts.iter().position(|p| *p == part_name).unwrap_or_else(|| {
+                                parts.push(part_name.clone());
+                                parts.len() - 1
+                            });
+                            part = Some(index);
+                            index
+                        }
+                    };
+
+                    // fan triangulation
+                    for i in 1..corners.len() - 1 {
+                        let corners = [corners[0], corners[i], corners[i + 1]];
+                        let mut triangle = Triangle::new(
+                            [
+                                positions[corners[0].position],
+                                positions[corners[1].position],
+                                positions[corners[2].position],
+                            ],
+                            Vec3::new(0.0, 0.0, 0.0),
+                        );
+                        triangle.part = index as u32;
+                        triangle.recalculate_normal();
+
+                        if let ([Some(n0), Some(n1), Some(n2)], false) = (
+                            [corners[0].normal, corners[1].normal, corners[2].normal],
+                            recalculate_normals,
+                        ) {
+                            let supplied = [normals[n0], normals[n1], normals[n2]].map(|n| n.normalize());
+                            let average = (supplied[0] + supplied[1] + supplied[2]).normalize();
+
+                            if !triangle.normal.iter().all(|c| c.is_finite()) {
+                                // fall back to the supplied normals for degenerated faces
+                                triangle.normal = average;
+                            } else if glm::dot(&average, &triangle.normal) > 0.0
+                                && supplied.iter().all(|n| n.iter().all(|c| c.is_finite()))
+                            {
+                                // used for shading unless they point against the winding
+                                triangle.set_vertex_normals(Some(supplied));
+                            }
+                        }
+
+                        if !triangle.has_finite_vertices() {
+                            return Err(ParseError::NonFinite {
+                                triangle: triangles.len() as u64,
+                            }
+                            .into());
+                        }
+                        triangles.push(triangle);
+                    }
+                }
+                Some("g") | Some("o") => {
+                    part_name = tokens[1..].iter().map(|(_, t)| *t).collect::<Vec<_>>().join(" ");
+                    part = None;
+                }
+                // texture coordinates, materials, smoothing groups, lines etc. don't affect the thumbnail
+                _ => {}
+            }
+
+            line.clear();
+        }
+
+        if parts.is_empty() {
+            parts.push(String::new());
+        }
+
+        Ok(InMemorySource::new(triangles, parts))
+    }
+}
+
+impl ObjParser {
+    pub fn from_file(filename: &str, recalculate_normals: bool) -> Result<InMemorySource> {
+        let file = fs::File::open(filename)?;
+        Self::from_buf(file, recalculate_normals)
+    }
+}
+
+// parses 'v', 'v/vt', 'v//vn' and 'v/vt/vn'
+fn parse_corner(token: &str, position_count: usize, normal_count: usize) -> Option<Corner> {
+    let mut indices = token.split('/');
+
+    let position = resolve_index(indices.next()?, position_count)?;
+    let _texture = indices.next();
+    let normal = match indices.next() {
+        Some(index) if !index.is_empty() => Some(resolve_index(index, normal_count)?),
+        _ => None,
+    };
+
+    Some(Corner { position, normal })
+}
+
+// OBJ indices are 1-based, negative indices are relative to the end of the list
+fn resolve_index(index: &str, count: usize) -> Option<usize> {
+    let index = index.parse::<i64>().ok()?;
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+
+    if resolved >= 0 && (resolved as usize) < count {
+        Some(resolved as usize)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mesh::*;
+    use crate::obj::ObjParser;
+    use crate::parser::{AsciiError, ParseError};
+    use std::io::Cursor;
+
+    const CUBE_OBJ: &[u8] = include_bytes!("test_models/cube.obj");
+
+    #[test]
+    fn obj_cube() {
+        let mut parser = ObjParser::from_buf(Cursor::new(CUBE_OBJ), false).unwrap();
+        let mesh = parser.read_all().unwrap();
+
+        // 6 quads
+        assert_eq!(mesh.len(), 12);
+        assert_eq!(parser.part_names(), &["cube".to_string()]);
+
+        // bottom face points down
+        assert_eq!(mesh[0].normal, Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(mesh[0].vertices[0], Vec3::new(0.0, 0.0, 0.0));
+        assert_eq!(mesh[0].vertices[1], Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(mesh[0].vertices[2], Vec3::new(1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn obj_negative_indices_and_groups() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\ng first\nf -3//-1 -2//-1 -1//-1\n\
+                   o second\nv 0 0 1\nv 1 0 1\nv 1 1 1\nv 0 1 1\nf 4/1 5/2 6/3 7/4\n";
+        let mut parser = ObjParser::from_buf(Cursor::new(obj), false).unwrap();
+        let parts = parser.read_parts().unwrap();
+
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name, "first");
+        assert_eq!(parts[0].mesh.len(), 1);
+        assert_eq!(parts[0].mesh[0].vertices[2], Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(parts[1].name, "second");
+        assert_eq!(parts[1].mesh.len(), 2);
+        assert_eq!(parts[1].mesh[1].part, 1);
+        assert_eq!(parts[1].mesh[1].vertices[2], Vec3::new(0.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn obj_vertex_normals() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 2\nvn 0 1 1\nf 1//1 2//1 3//2\nf 1 2 3\nf 1//1 3//1 2//1\n";
+        let mesh = ObjParser::from_buf(Cursor::new(obj), false)
+            .unwrap()
+            .read_all()
+            .unwrap();
+
+        let up = Vec3::new(0.0, 0.0, 1.0);
+        assert_eq!(
+            mesh[0].vertex_normals(),
+            Some([up, up, Vec3::new(0.0, 1.0, 1.0).normalize()])
+        );
+        assert_eq!(mesh[1].vertex_normals(), None);
+        // against the winding
+        assert_eq!(mesh[2].vertex_normals(), None);
+
+        let mesh = ObjParser::from_buf(Cursor::new(obj), true).unwrap().read_all().unwrap();
+        assert_eq!(mesh[0].vertex_normals(), None);
+    }
+
+    #[test]
+    fn obj_invalid_index() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\n\nf 1 2 4\n";
+        let err = ObjParser::from_buf(Cursor::new(obj), false).err().unwrap();
+        let err = err.downcast_ref::<AsciiError>().unwrap();
+
+        assert_eq!((err.line, err.column), (5, 7));
+    }
+
+    #[test]
+    fn obj_non_finite_vertex() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 NaN 0\nf 1 2 3\n";
+        let err = ObjParser::from_buf(Cursor::new(obj), false).err().unwrap();
+
+        assert!(matches!(
+            err.downcast_ref::<ParseError>(),
+            Some(ParseError::NonFinite { triangle: 0 })
+        ));
+    }
+
+    #[test]
+    fn obj_latin1() {
+        let obj = b"# \xe9bauche\nv 0 0 0\nv 1 0 0\nv 0 1 0\ng pi\xe8ce\nusemtl m\xe9tal\nf 1 2 3\n";
+        let mut parser = ObjParser::from_buf(Cursor::new(&obj[..]), false).unwrap();
+
+        assert_eq!(parser.read_all().unwrap().len(), 1);
+        assert_eq!(parser.part_names(), &["pi\u{fffd}ce".to_string()]);
+    }
+}
@@ -0,0 +1,251 @@
+use crate::mesh::*;
+use crate::parser::{tokenize, AsciiError, ParseError};
+use anyhow::{bail, Error, Result};
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+
+/// Object File Format (OFF) reader
+/// Supports the 'OFF', 'COFF', 'NOFF' and 'CNOFF' variants with per vertex and per face colors.
+pub struct OffParser;
+
+impl OffParser {
+    pub fn from_buf<T: Read>(inner: T) -> Result<InMemorySource> {
+        let mut lines = Lines {
+            reader: BufReader::new(inner),
+            line: 0,
+        };
+
+        // the keyword may be followed by the counts on the same line
+        let (keyword, mut counts) = match lines.next()? {
+            Some(tokens) => {
+                let keyword = tokens[0].1.clone();
+                (keyword, tokens[1..].to_vec())
+            }
+            None => bail!("OFF: empty file"),
+        };
+
+        let has_colors = keyword.starts_with('C');
+        let has_normals = keyword.trim_start_matches('C').starts_with('N');
+        if !keyword.ends_with("OFF") || keyword.contains("ST") || keyword.starts_with('4') {
+            bail!("OFF: unsupported variant '{}'", keyword);
+        }
+
+        if counts.is_empty() {
+            counts = lines.expect("vertex and face counts")?;
+        }
+        let vertex_count: usize = lines.parse(&counts, 0)?;
+        let face_count: usize = lines.parse(&counts, 1)?;
+
+        // the counts are untrusted, don't reserve too much memory upfront
+        let mut positions = Vec::with_capacity(vertex_count.min(1 << 20));
+        let mut colors = vec![];
+
+        for _ in 0..vertex_count {
+            let tokens = lines.expect("vertex")?;
+            positions.push(Vec3::new(
+                lines.parse(&tokens, 0)?,
+                lines.parse(&tokens, 1)?,
+                lines.parse(&tokens, 2)?,
+            ));
+
+            if has_colors {
+                let offset = if has_normals { 6 } else { 3 };
+                colors.push(lines.color(&tokens, offset)?);
+            }
+        }
+
+        let mut triangles = Vec::with_capacity(face_count.min(1 << 20));
+        for _ in 0..face_count {
+            let tokens = lines.expect("face")?;
+            let n: usize = lines.parse(&tokens, 0)?;
+            // the count is untrusted, the indices have to be on the line
+            if n >= tokens.len() {
+                let end = tokens.last().map_or(1, |(c, t)| c + t.len());
+                return Err(lines.error(end, format!("expected {} vertex indices", n)));
+            }
+
+            let mut polygon = Vec::with_capacity(n);
+            for i in 1..=n {
+                let index: usize = lines.parse(&tokens, i)?;
+                if index >= positions.len() {
+                    let (column, token) = &tokens[i];
+                    return Err(lines.error(*column, format!("vertex index '{}' out of range", token)));
+                }
+                polygon.push(index);
+            }
+
+            // faces can optionally be followed by a color
+            let face_color = if tokens.len() >= n + 4 {
+                Some(lines.color(&tokens, n + 1)?)
+            } else {
+                None
+            };
+
+            // fan triangulation
+            for i in 1..n.saturating_sub(1) {
+                let corners = [polygon[0], polygon[i], polygon[i + 1]];
+                let mut triangle = Triangle::new(
+                    [positions[corners[0]], positions[corners[1]], positions[corners[2]]],
+                    Vec3::new(0.0, 0.0, 0.0),
+                );
+                triangle.recalculate_normal();
+                triangle.set_color(face_color);
+                if !colors.is_empty() {
+                    triangle.set_vertex_colors(Some([colors[corners[0]], colors[corners[1]], colors[corners[2]]]));
+                }
+                if !triangle.has_finite_vertices() {
+                    return Err(ParseError::NonFinite {
+                        triangle: triangles.len() as u64,
+                    }
+                    .into());
+                }
+                triangles.push(triangle);
+            }
+        }
+
+        Ok(InMemorySource::new(triangles, vec![]))
+    }
+}
+
+impl OffParser {
+    pub fn from_file(filename: &str) -> Result<InMemorySource> {
+        let file = fs::File::open(filename)?;
+        Self::from_buf(file)
+    }
+}
+
+struct Lines<T: BufRead> {
+    reader: T,
+    line: usize,
+}
+
+impl<T: BufRead> Lines<T> {
+    // next non-blank line without comments
+    fn next(&mut self) -> Result<Option<Vec<(usize, String)>>> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            self.line += 1;
+
+            let statement = line.split('#').next().unwrap_or_default();
+            let tokens = tokenize(statement);
+            if !tokens.is_empty() {
+                return Ok(Some(tokens.into_iter().map(|(c, t)| (c, t.to_string())).collect()));
+            }
+        }
+    }
+
+    fn expect(&mut self, what: &str) -> Result<Vec<(usize, String)>> {
+        match self.next()? {
+            Some(tokens) => Ok(tokens),
+            None => Err(self.error(1, format!("unexpected end of file, expected {}", what))),
+        }
+    }
+
+    fn parse<V: std::str::FromStr>(&self, tokens: &[(usize, String)], index: usize) -> Result<V> {
+        match tokens.get(index) {
+            Some((column, token)) => token
+                .parse::<V>()
+                .map_err(|_| self.error(*column, format!("invalid number '{}'", token))),
+            None => {
+                let end = tokens.last().map_or(1, |(c, t)| c + t.len());
+                Err(self.error(end, "expected a number".to_string()))
+            }
+        }
+    }
+
+    // colors are either floats in [0,1] or integers in [0,255]
+    fn color(&self, tokens: &[(usize, String)], offset: usize) -> Result<Vec3> {
+        let rgb = Vec3::new(
+            self.parse(tokens, offset)?,
+            self.parse(tokens, offset + 1)?,
+            self.parse(tokens, offset + 2)?,
+        );
+
+        let is_integer = tokens[offset..offset + 3].iter().all(|(_, t)| !t.contains('.'));
+        if is_integer && rgb.max() > 1.0 {
+            Ok(rgb / 255.0)
+        } else {
+            Ok(rgb)
+        }
+    }
+
+    fn error(&self, column: usize, message: String) -> Error {
+        AsciiError {
+            line: self.line,
+            column,
+            message,
+        }
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mesh::*;
+    use crate::off::OffParser;
+    use crate::parser::{AsciiError, ParseError};
+    use std::io::Cursor;
+
+    #[test]
+    fn off_quad() {
+        let off = "OFF\n# a quad\n4 1 0\n0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 0 1 2 3 255 0 0\n";
+        let mut parser = OffParser::from_buf(Cursor::new(off)).unwrap();
+        let mesh = parser.read_all().unwrap();
+
+        assert_eq!(mesh.len(), 2);
+        assert_eq!(mesh[0].normal, Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(mesh[1].vertices[2], Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(mesh[1].color(), Some(Vec3::new(1.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn off_vertex_colors() {
+        let off = "COFF 3 1 0\n0 0 0 1 0 0 1\n1 0 0 0 1 0 1\n0 1 0 0 0 1 1\n3 0 1 2\n";
+        let mut parser = OffParser::from_buf(Cursor::new(off)).unwrap();
+        let mesh = parser.read_all().unwrap();
+
+        assert_eq!(mesh.len(), 1);
+        assert_eq!(mesh[0].color(), None);
+        assert_eq!(
+            mesh[0].vertex_colors(),
+            Some([
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0)
+            ])
+        );
+    }
+
+    #[test]
+    fn off_invalid_index() {
+        let off = "OFF\n3 1 0\n0 0 0\n1 0 0\n0 1 0\n3 0 1 3\n";
+        let err = OffParser::from_buf(Cursor::new(off)).err().unwrap();
+        let err = err.downcast_ref::<AsciiError>().unwrap();
+
+        assert_eq!((err.line, err.column), (6, 7));
+    }
+
+    #[test]
+    fn off_vertex_count_exceeds_line() {
+        let off = "OFF\n3 1 0\n0 0 0\n1 0 0\n0 1 0\n4000000000000 0 1 2\n";
+        let err = OffParser::from_buf(Cursor::new(off)).err().unwrap();
+        let err = err.downcast_ref::<AsciiError>().unwrap();
+
+        assert_eq!((err.line, err.column), (6, 20));
+    }
+
+    #[test]
+    fn off_non_finite_vertex() {
+        let off = "OFF\n4 2 0\n0 0 0\n1 0 0\n0 1 0\n0 0 inf\n3 0 1 2\n3 0 1 3\n";
+        let err = OffParser::from_buf(Cursor::new(off)).err().unwrap();
+
+        assert!(matches!(
+            err.downcast_ref::<ParseError>(),
+            Some(ParseError::NonFinite { triangle: 1 })
+        ));
+    }
+}
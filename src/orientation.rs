@@ -0,0 +1,171 @@
+use crate::defects::Defects;
+use crate::mesh::*;
+
+/// The repairs made by orient
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Orientation {
+    /// normals pointing to the other side than the repaired winding of their triangle
+    pub inconsistent_normals: usize,
+    /// faces wound differently than their neighbours
+    pub flipped_faces: usize,
+    /// the whole model was inside out (negative signed volume), only closed models are checked
+    pub inverted: bool,
+}
+
+impl Orientation {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Turns inconsistent and inverted triangles around such that all of them face outwards
+/// The renderer culls triangles by their normal and rasterizes them by their winding, either
+/// pointing inwards leaves holes or makes the model look inside out.
+/// Open models have no inside, their winding is only made consistent.
+pub fn orient(mesh: &mut Mesh) -> Orientation {
+    let mut orientation = Orientation::default();
+
+    // the winding is repaired first, the normals follow it
+    let defects = Defects::from_mesh(mesh);
+    let mut flipped = vec![false; mesh.len()];
+    for i in &defects.flipped_faces {
+        mesh[*i].flip();
+        flipped[*i] = true;
+    }
+    orientation.flipped_faces = defects.flipped_faces.len();
+
+    // the signed volume of open models depends on their position
+    let closed = defects.open_edges.is_empty() && defects.non_manifold_edges.is_empty();
+    if closed && signed_volume(mesh) < 0.0 {
+        for triangle in mesh.iter_mut() {
+            triangle.flip();
+        }
+        orientation.inverted = true;
+    }
+
+    // the winding wins, the normals are often written carelessly
+    for (i, triangle) in mesh.iter_mut().enumerate() {
+        let winding =
+            (triangle.vertices[1] - triangle.vertices[0]).cross(&(triangle.vertices[2] - triangle.vertices[0]));
+        if glm::dot(&winding, &triangle.normal) < 0.0 {
+            triangle.normal = -triangle.normal;
+            // flipped faces are counted once, whichever way their normal pointed
+            if !flipped[i] {
+                orientation.inconsistent_normals += 1;
+            }
+        }
+    }
+
+    orientation
+}
+
+/// Volume enclosed by the mesh, negative if the triangles are wound clockwise
+pub fn signed_volume(mesh: &Mesh) -> f64 {
+    mesh.into_iter()
+        .map(|t| {
+            let [v0, v1, v2] = t.vertices.map(glm::convert::<Vec3, glm::DVec3>);
+            v0.dot(&v1.cross(&v2)) / 6.0
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mesh::*;
+    use crate::orientation::{orient, signed_volume, Orientation};
+
+    // whether every triangle's normal points away from the center of the cube
+    fn faces_outwards(mesh: &Mesh) -> bool {
+        mesh.into_iter().all(|t| {
+            let center = (t.vertices[0] + t.vertices[1] + t.vertices[2]) / 3.0;
+            let winding = (t.vertices[1] - t.vertices[0]).cross(&(t.vertices[2] - t.vertices[0]));
+            glm::dot(&center, &t.normal) > 0.0 && glm::dot(&center, &winding) > 0.0
+        })
+    }
+
+    #[test]
+    fn orient_consistent() {
+        let mut mesh = cuboid(Vec3::repeat(-0.5), Vec3::repeat(0.5));
+        assert!((signed_volume(&mesh) - 1.0).abs() < 1e-6);
+        assert!(orient(&mut mesh).is_empty());
+        assert!(faces_outwards(&mesh));
+    }
+
+    #[test]
+    fn orient_inverted() {
+        let mut mesh = cuboid(Vec3::repeat(-0.5), Vec3::repeat(0.5));
+        for triangle in mesh.iter_mut() {
+            triangle.flip();
+        }
+
+        // some of the normals point outwards nevertheless
+        mesh[0].normal = -mesh[0].normal;
+        mesh[3].normal = -mesh[3].normal;
+
+        assert_eq!(
+            orient(&mut mesh),
+            Orientation {
+                inconsistent_normals: 2,
+                flipped_faces: 0,
+                inverted: true,
+            }
+        );
+        assert!(faces_outwards(&mesh));
+    }
+
+    #[test]
+    fn orient_flipped_faces() {
+        let mut mesh = cuboid(Vec3::repeat(-0.5), Vec3::repeat(0.5));
+        mesh[4].flip();
+        mesh[7].flip();
+
+        let orientation = orient(&mut mesh);
+        assert_eq!(orientation.flipped_faces, 2);
+        assert!(!orientation.inverted);
+        assert!(faces_outwards(&mesh));
+    }
+
+    #[test]
+    fn orient_flipped_face_with_outward_normal() {
+        // wound the wrong way round, but the normal already points outwards
+        let mut mesh = cuboid(Vec3::repeat(-0.5), Vec3::repeat(0.5));
+        mesh[4].vertices.swap(1, 2);
+
+        assert_eq!(
+            orient(&mut mesh),
+            Orientation {
+                inconsistent_normals: 0,
+                flipped_faces: 1,
+                inverted: false,
+            }
+        );
+        assert!(faces_outwards(&mesh));
+    }
+
+    #[test]
+    fn orient_open_mesh() {
+        // the top of the cube facing upwards, below the origin its signed volume is negative
+        let top: Vec<Triangle> = cuboid(Vec3::repeat(-0.5), Vec3::repeat(0.5))
+            .into_iter()
+            .filter(|t| t.normal.z > 0.5)
+            .collect();
+        let mut mesh = Mesh::new(top);
+        for triangle in mesh.iter_mut() {
+            for v in triangle.vertices.iter_mut() {
+                v.z -= 5.5;
+            }
+        }
+        assert!(signed_volume(&mesh) < 0.0);
+
+        assert!(orient(&mut mesh).is_empty());
+        assert!(mesh.into_iter().all(|t| t.normal.z > 0.5));
+
+        // the same for a cube missing a face
+        let mut mesh = cuboid(Vec3::repeat(-0.5), Vec3::repeat(0.5));
+        for triangle in mesh.iter_mut() {
+            triangle.flip();
+        }
+        let mut mesh = Mesh::new(mesh.into_iter().skip(2).collect());
+        assert!(!orient(&mut mesh).inverted);
+    }
+}
@@ -0,0 +1,93 @@
+use crate::aabb::AABB;
+use crate::mesh::*;
+use crate::parser::{check_triangle, decode_triangle, FacetColors, ParseError, TRIANGLE_SIZE};
+use rayon::prelude::*;
+
+// triangles decoded by a worker at once
+const CHUNK_SIZE: usize = 1 << 14;
+
+/// Decodes the triangle data of a binary STL file (everything following the triangle count) on all cores
+/// Every worker computes the AABB of its chunk along the way, the partial AABBs are merged afterwards.
+pub(crate) fn decode_binary(
+    data: &[u8],
+    facet_colors: FacetColors,
+    recalculate_normals: bool,
+) -> Result<(Mesh, AABB), ParseError> {
+    let size = TRIANGLE_SIZE as usize;
+    let zero = Vec3::new(0.0, 0.0, 0.0);
+    let mut triangles = vec![Triangle::new([zero; 3], zero); data.len() / size];
+
+    let chunks: Vec<Result<AABB, ParseError>> = triangles
+        .par_chunks_mut(CHUNK_SIZE)
+        .zip(data.par_chunks(CHUNK_SIZE * size))
+        .enumerate()
+        .map(|(chunk, (triangles, data))| {
+            let mut aabb = AABB::empty();
+            for (i, (triangle, bytes)) in triangles.iter_mut().zip(data.chunks_exact(size)).enumerate() {
+                *triangle = decode_triangle(bytes, facet_colors);
+                check_triangle(triangle, (chunk * CHUNK_SIZE + i) as u64, recalculate_normals)?;
+                aabb.extend(triangle);
+            }
+            Ok(aabb)
+        })
+        .collect();
+
+    // the error of the first chunk is reported, just like reading the file sequentially would
+    let mut aabb = AABB::empty();
+    for chunk in chunks {
+        aabb = aabb.merge(&chunk?);
+    }
+
+    Ok((Mesh::new(triangles), aabb))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::aabb::AABB;
+    use crate::mesh::*;
+    use crate::parallel::{decode_binary, CHUNK_SIZE};
+    use crate::parser::{FacetColors, ParseError};
+    use byteorder::{LittleEndian, WriteBytesExt};
+
+    fn triangle_data(count: usize) -> Vec<u8> {
+        let mut data = vec![];
+        for i in 0..count {
+            let x = i as f32;
+            // no normal, hence it is calculated while decoding
+            for c in &[0.0, 0.0, 0.0, x, 0.0, 0.0, x + 1.0, 0.0, 0.0, x, 1.0, -x] {
+                data.write_f32::<LittleEndian>(*c).unwrap();
+            }
+            data.write_u16::<LittleEndian>(0).unwrap();
+        }
+        data
+    }
+
+    #[test]
+    fn parallel_decode() {
+        let count = CHUNK_SIZE * 2 + 7;
+        let (mesh, aabb) = decode_binary(&triangle_data(count), FacetColors::VisCam, false).unwrap();
+
+        assert_eq!(mesh.len(), count);
+        assert_eq!(mesh[CHUNK_SIZE + 3].vertices[0].x, (CHUNK_SIZE + 3) as f32);
+        assert!(mesh[count - 1].normal.z > 0.0);
+
+        let expected = AABB::from_mesh(&mesh);
+        assert_eq!(aabb.lower, expected.lower);
+        assert_eq!(aabb.upper, expected.upper);
+        assert_eq!(aabb.upper, Vec3::new(count as f32, 1.0, 0.0));
+    }
+
+    #[test]
+    fn parallel_decode_first_error() {
+        let mut data = triangle_data(CHUNK_SIZE * 3);
+        for index in &[CHUNK_SIZE * 2 + 1, CHUNK_SIZE + 5] {
+            let offset = index * 50 + 12;
+            data[offset..offset + 4].copy_from_slice(&f32::NAN.to_le_bytes());
+        }
+
+        match decode_binary(&data, FacetColors::VisCam, false) {
+            Err(ParseError::NonFinite { triangle }) => assert_eq!(triangle, CHUNK_SIZE as u64 + 5),
+            _ => panic!("expected a non-finite error"),
+        }
+    }
+}
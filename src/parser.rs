@@ -1,46 +1,277 @@
+use crate::aabb::AABB;
 use crate::mesh::*;
-use anyhow::*;
-use byteorder::{LittleEndian, ReadBytesExt};
-//use scan_fmt::*;
+use crate::parallel::decode_binary;
+use anyhow::Result;
+use byteorder::{ByteOrder, LittleEndian};
+use std::fmt;
 use std::fs;
 use std::io;
 use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
 
-const HEADER_SIZE: u64 = 80;
-const TRIANGLE_SIZE: u64 = 50;
+pub(crate) const HEADER_SIZE: u64 = 80;
+pub(crate) const TRIANGLE_SIZE: u64 = 50;
 
+// how much of the file is inspected to tell ascii from binary files
+const SNIFF_LENGTH: u64 = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum StlType {
     Binary,
     Ascii,
 }
 
+/// How binary files whose triangle count doesn't match the file size are handled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Recovery {
+    /// rejects the file
+    Strict,
+    /// reads as many triangles as the file actually contains
+    Lenient,
+}
+
+// conventions for storing colors in the attribute word of binary files
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum FacetColors {
+    // bit 15 set if the color is valid, 5 bits per channel in BGR order
+    VisCam,
+    // bit 15 cleared if the facet has its own color, 5 bits per channel in RGB order,
+    // the header may define the color used for all other facets
+    Materialise { default: Option<Vec3> },
+}
+
+/// Syntax error in a text based model file (e.g. ascii STL)
+/// line and column are 1-based
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsciiError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsciiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for AsciiError {}
+
+/// Errors encountered while reading the triangles of a STL file
+#[derive(Debug)]
+pub enum ParseError {
+    /// the file ends in the middle of the model
+    Truncated {
+        triangles: u64,
+    },
+    /// the triangle count of a binary file doesn't match its size
+    BadTriangleCount {
+        header: u64,
+        actual: u64,
+    },
+    /// a binary file ends with a partial triangle
+    TrailingBytes {
+        bytes: u64,
+    },
+    /// a vertex is NaN or infinite
+    NonFinite {
+        triangle: u64,
+    },
+    /// a line of an ascii file isn't valid UTF-8
+    Encoding {
+        line: usize,
+    },
+    Syntax(AsciiError),
+    Io(io::Error),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Truncated { triangles } => write!(f, "file is truncated after {} triangles", triangles),
+            ParseError::BadTriangleCount { header, actual } => write!(
+                f,
+                "header declares {} triangles but the file contains {}",
+                header, actual
+            ),
+            ParseError::TrailingBytes { bytes } => write!(f, "file ends with {} unexpected bytes", bytes),
+            ParseError::NonFinite { triangle } => write!(f, "triangle {} has non-finite coordinates", triangle),
+            ParseError::Encoding { line } => write!(f, "line {}: invalid UTF-8", line),
+            ParseError::Syntax(e) => e.fmt(f),
+            ParseError::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::Syntax(e) => Some(e),
+            ParseError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> Self {
+        ParseError::Io(e)
+    }
+}
+
 pub struct Parser<T>
 where
-    T: Read + Seek,
+    T: Read,
 {
     reader: BufReader<T>,
     stl_type: StlType,
     header_length: u64,
+    header_lines: usize,
+    line: usize,
+    parts: Vec<String>,
+    part: usize,
+    in_solid: bool,
     recalculate_normals: bool,
+    pub(crate) triangle_count_hint: Option<u64>,
+    facet_colors: FacetColors,
+    // declared triangle count of binary streams whose size is unknown
+    stream_check: Option<(u64, Recovery)>,
+    triangle: u64,
+    pub(crate) warnings: Vec<String>,
 }
 
 impl<T: Read + Seek> Parser<T> {
+    /// Opens the file in strict mode
     pub fn from_buf(inner: T, recalculate_normals: bool) -> Result<Self> {
+        Self::with_recovery(inner, recalculate_normals, Recovery::Strict)
+    }
+
+    pub fn with_recovery(inner: T, recalculate_normals: bool, recovery: Recovery) -> Result<Self> {
         let mut reader = BufReader::new(inner);
 
         let stl_type = deduce_stl_type(&mut reader)?;
+        let filesize = reader.seek(SeekFrom::End(0))?;
         reader.seek(SeekFrom::Start(0))?;
 
-        // figure out header size
-        let mut header_length = 0;
+        let mut parser = Self::with_header(reader, stl_type, Some(filesize), recalculate_normals, recovery)?;
+        parser.header_length = parser.reader.stream_position()?;
+
+        Ok(parser)
+    }
+
+    pub fn rewind(&mut self) -> Result<()> {
+        self.reader.seek(SeekFrom::Start(self.header_length))?;
+        self.line = self.header_lines;
+        self.part = 0;
+        self.in_solid = true;
+        self.triangle = 0;
+        Ok(())
+    }
+
+    pub fn triangle_count(&mut self) -> Result<u64> {
+        self.rewind()?;
+
+        match self.stl_type {
+            // for binary files the count of the header has been checked against the file size
+            StlType::Binary => Ok(self.triangle_count_hint.unwrap_or_default()),
+            StlType::Ascii => {
+                // we have no other choice as parsing the hole file
+                let mut count = 0;
+                while self.next_triangle()?.is_some() {
+                    count += 1;
+                }
+                Ok(count)
+            }
+        }
+    }
+
+    pub fn read_all(&mut self) -> Result<Mesh> {
+        Ok(self.read_all_with_bounds()?.0)
+    }
+
+    /// Reads the whole file and computes its AABB in the same pass
+    /// Binary files are decoded on all cores.
+    pub fn read_all_with_bounds(&mut self) -> Result<(Mesh, AABB)> {
+        self.rewind()?;
+
+        if let StlType::Binary = self.stl_type {
+            let size = self.triangle_count_hint.unwrap_or_default() * TRIANGLE_SIZE;
+            let mut data = Vec::with_capacity(size as usize);
+            (&mut self.reader).take(size).read_to_end(&mut data)?;
+            if data.len() as u64 != size {
+                return Err(ParseError::Truncated {
+                    triangles: data.len() as u64 / TRIANGLE_SIZE,
+                }
+                .into());
+            }
+
+            let (mesh, aabb) = decode_binary(&data, self.facet_colors, self.recalculate_normals)?;
+            self.triangle = mesh.len() as u64;
+            return Ok((mesh, aabb));
+        }
+
+        let mut triangles = vec![];
+        let mut aabb = AABB::empty();
+        while let Some(triangle) = self.next_triangle()? {
+            aabb.extend(&triangle);
+            triangles.push(triangle);
+        }
+
+        Ok((Mesh::new(triangles), aabb))
+    }
+
+    /// Reads the whole file and splits it into its named solids
+    pub fn read_parts(&mut self) -> Result<Vec<Part>> {
+        let mesh = self.read_all()?;
+        Ok(mesh.split_parts(&self.parts))
+    }
+}
+
+impl<T: Read> Parser<T> {
+    // reads the header, the triangle count of binary files is checked against the file size if it is known
+    // and at the end of the file otherwise
+    pub(crate) fn with_header(
+        mut reader: BufReader<T>,
+        stl_type: StlType,
+        filesize: Option<u64>,
+        recalculate_normals: bool,
+        recovery: Recovery,
+    ) -> Result<Self> {
+        let mut header_lines = 0;
+        let mut parts = vec![String::new()];
+        let mut triangle_count_hint = None;
+        let mut facet_colors = FacetColors::VisCam;
+        let mut stream_check = None;
+        let mut warnings = vec![];
         match stl_type {
             StlType::Binary => {
-                header_length = HEADER_SIZE + 4; // header size + triangle count (u32)
+                // header + triangle count (u32)
+                let mut header = vec![];
+                (&mut reader).take(HEADER_SIZE + 4).read_to_end(&mut header)?;
+
+                match filesize {
+                    Some(filesize) => {
+                        let layout = BinaryLayout::new(&header, filesize, recovery)?;
+                        facet_colors = layout.facet_colors;
+                        triangle_count_hint = Some(layout.triangles);
+                        warnings = layout.warnings;
+                    }
+                    None => {
+                        let declared = BinaryLayout::declared_triangles(&header)?;
+                        facet_colors = facet_colors_from_header(&header[..HEADER_SIZE as usize]);
+                        stream_check = Some((declared, recovery));
+                        // lenient mode reads past the declared count
+                        if recovery == Recovery::Strict {
+                            triangle_count_hint = Some(declared);
+                        }
+                    }
+                }
             }
             StlType::Ascii => {
-                while let Some(line) = read_ascii_line(&mut reader).ok() {
-                    if line.starts_with("solid") {
-                        header_length = reader.seek(SeekFrom::Current(0))? as u64;
+                while let Some((line, _)) = read_ascii_line(&mut reader)? {
+                    header_lines += 1;
+                    let tokens = owned_tokens(&line);
+                    if is_keyword(&tokens, "solid") {
+                        parts[0] = solid_name(&tokens);
                         break;
                     }
                 }
@@ -50,68 +281,236 @@ impl<T: Read + Seek> Parser<T> {
         Ok(Self {
             reader,
             stl_type,
-            header_length,
+            header_length: 0,
+            header_lines,
+            line: header_lines,
+            parts,
+            part: 0,
+            in_solid: true,
             recalculate_normals,
+            triangle_count_hint,
+            facet_colors,
+            stream_check,
+            triangle: 0,
+            warnings,
         })
     }
 
-    pub fn rewind(&mut self) -> Result<()> {
-        self.reader.seek(SeekFrom::Start(self.header_length))?;
-        Ok(())
-    }
-
-    pub fn next_triangle(&mut self) -> Option<Triangle> {
+    /// Reads the next triangle, returns None at the end of the model
+    pub fn next_triangle(&mut self) -> Result<Option<Triangle>, ParseError> {
         let mut triangle = match self.stl_type {
-            StlType::Ascii => read_ascii_triangle(&mut self.reader).ok(),
-            StlType::Binary => read_triangle(&mut self.reader).ok(),
+            StlType::Ascii => self.read_ascii_triangle()?,
+            StlType::Binary => self.read_binary_triangle()?,
         };
 
-        // calculate normal from vertices using right hand rule is case it is missing
         if let Some(triangle) = &mut triangle {
-            if self.recalculate_normals
-                || triangle.normal == Vec3::new(0.0, 0.0, 0.0)
-                || triangle.normal == Vec3::new(f32::NAN, f32::NAN, f32::NAN)
-            {
-                triangle.normal = (triangle.vertices[1] - triangle.vertices[0])
-                    .cross(&(triangle.vertices[2] - triangle.vertices[0]))
-                    .normalize();
+            check_triangle(triangle, self.triangle, self.recalculate_normals)?;
+            self.triangle += 1;
+        }
+
+        Ok(triangle)
+    }
+
+    fn read_binary_triangle(&mut self) -> Result<Option<Triangle>, ParseError> {
+        if let Some((declared, recovery)) = self.stream_check {
+            return self.read_streamed_triangle(declared, recovery);
+        }
+
+        if Some(self.triangle) == self.triangle_count_hint {
+            return Ok(None);
+        }
+
+        match read_triangle(&mut self.reader, self.facet_colors) {
+            Ok(triangle) => Ok(Some(triangle)),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Err(ParseError::Truncated {
+                triangles: self.triangle,
+            }),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // the size of streams isn't known upfront, hence the triangle count is checked at the end of the stream
+    fn read_streamed_triangle(&mut self, declared: u64, recovery: Recovery) -> Result<Option<Triangle>, ParseError> {
+        let mut bytes = [0u8; TRIANGLE_SIZE as usize];
+        let read = if self.triangle < declared || recovery == Recovery::Lenient {
+            read_up_to(&mut self.reader, &mut bytes)?
+        } else {
+            0
+        };
+        if read == bytes.len() {
+            return Ok(Some(decode_triangle(&bytes, self.facet_colors)));
+        }
+
+        // whatever follows the declared triangles
+        let rest = read as u64 + io::copy(&mut self.reader, &mut io::sink())?;
+        let (_, mismatch) = binary_triangle_count(declared, self.triangle * TRIANGLE_SIZE + rest);
+
+        match (mismatch, recovery) {
+            (Some(e), Recovery::Strict) => Err(e),
+            (Some(e), Recovery::Lenient) => {
+                self.warnings
+                    .push(format!("{}, reading {} triangles", e, self.triangle));
+                Ok(None)
             }
+            (None, _) => Ok(None),
         }
+    }
 
-        triangle
+    /// Names of the solids encountered so far
+    /// Binary files and ascii files without a name have a single unnamed part
+    pub fn part_names(&self) -> &[String] {
+        &self.parts
     }
 
-    pub fn triangle_count(&mut self) -> Result<u64> {
-        self.rewind()?;
+    // reads the next non-blank line and splits it into tokens
+    fn next_ascii_tokens(&mut self) -> Result<Option<Vec<(usize, String)>>, ParseError> {
+        while let Some((line, is_utf8)) = read_ascii_line(&mut self.reader)? {
+            self.line += 1;
+            let tokens = owned_tokens(&line);
 
-        match self.stl_type {
-            StlType::Binary => {
-                // for binary files we can quickly read the first u32
-                // after the header which contains the triangle count
-                self.reader.seek(SeekFrom::Current(-4))?;
-                let count = self.reader.read_u32::<LittleEndian>()? as u64;
-                Ok(count)
+            // be forgiving about non utf8 characters in the name of the solid
+            if !is_utf8 && !is_keyword(&tokens, "solid") && !is_keyword(&tokens, "endsolid") {
+                return Err(ParseError::Encoding { line: self.line });
             }
-            StlType::Ascii => {
-                // we have no other choice as parsing the hole file
-                let mut count = 0;
-                while self.next_triangle().is_some() {
-                    count += 1;
+
+            if !tokens.is_empty() {
+                return Ok(Some(tokens));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn expect_ascii_tokens(&mut self) -> Result<Vec<(usize, String)>, ParseError> {
+        match self.next_ascii_tokens()? {
+            Some(tokens) => Ok(tokens),
+            None => Err(ParseError::Truncated {
+                triangles: self.triangle,
+            }),
+        }
+    }
+
+    fn expect_keywords(&self, tokens: &[(usize, String)], keywords: &[&str]) -> Result<(), ParseError> {
+        let end = tokens.last().map_or(1, |(c, t)| c + t.len());
+
+        for (i, keyword) in keywords.iter().enumerate() {
+            match tokens.get(i) {
+                Some((_, token)) if token.eq_ignore_ascii_case(keyword) => {}
+                Some((c, token)) => {
+                    return Err(self.error(*c, format!("expected '{}', found '{}'", keyword, token)));
                 }
-                Ok(count)
+                None => return Err(self.error(end, format!("expected '{}'", keyword))),
             }
         }
+
+        if let Some((c, token)) = tokens.get(keywords.len()) {
+            return Err(self.error(*c, format!("unexpected '{}'", token)));
+        }
+
+        Ok(())
     }
 
-    pub fn read_all(&mut self) -> Result<Mesh> {
-        self.rewind()?;
-        let mut triangles = vec![];
+    fn parse_vec3(&self, tokens: &[(usize, String)], offset: usize) -> Result<Vec3, ParseError> {
+        let v = parse_vec3(tokens, offset).map_err(|(column, message)| self.error(column, message))?;
 
-        while let Some(triangle) = self.next_triangle() {
-            triangles.push(triangle);
+        if let Some((c, token)) = tokens.get(offset + 3) {
+            return Err(self.error(*c, format!("unexpected '{}'", token)));
+        }
+
+        Ok(v)
+    }
+
+    fn read_ascii_triangle(&mut self) -> Result<Option<Triangle>, ParseError> {
+        // "facet normal nx ny nz", possibly preceded by the end of a solid and the start of the next one
+        let tokens = loop {
+            let tokens = match self.next_ascii_tokens()? {
+                Some(tokens) => tokens,
+                None => return Ok(None),
+            };
+
+            if is_keyword(&tokens, "endsolid") {
+                self.in_solid = false;
+            } else if is_keyword(&tokens, "solid") {
+                self.part += 1;
+                self.in_solid = true;
+                if self.part == self.parts.len() {
+                    self.parts.push(solid_name(&tokens));
+                }
+            } else if self.in_solid && is_keyword(&tokens, "facet") {
+                break tokens;
+            } else {
+                let expected = if self.in_solid { "facet" } else { "solid" };
+                let (column, keyword) = &tokens[0];
+                return Err(self.error(*column, format!("expected '{}', found '{}'", expected, keyword)));
+            }
+        };
+
+        // the normal is optional for some exporters
+        let normal = if tokens.len() > 1 {
+            self.expect_keywords(&tokens[1..2], &["normal"])?;
+            self.parse_vec3(&tokens, 2)?
+        } else {
+            Vec3::new(0.0, 0.0, 0.0)
+        };
+
+        let tokens = self.expect_ascii_tokens()?;
+        self.expect_keywords(&tokens, &["outer", "loop"])?;
+
+        let mut vertices = [Vec3::new(0.0, 0.0, 0.0); 3];
+        for v in &mut vertices {
+            let tokens = self.expect_ascii_tokens()?;
+            self.expect_keywords(&tokens[..1], &["vertex"])?;
+            *v = self.parse_vec3(&tokens, 1)?;
         }
 
-        Ok(Mesh::new(triangles))
+        let tokens = self.expect_ascii_tokens()?;
+        self.expect_keywords(&tokens, &["endloop"])?;
+
+        let tokens = self.expect_ascii_tokens()?;
+        self.expect_keywords(&tokens, &["endfacet"])?;
+
+        let mut triangle = Triangle::new(vertices, normal);
+        triangle.part = self.part as u32;
+
+        Ok(Some(triangle))
+    }
+
+    fn error(&self, column: usize, message: String) -> ParseError {
+        ParseError::Syntax(AsciiError {
+            line: self.line,
+            column,
+            message,
+        })
+    }
+}
+
+impl<T: Read + Seek> MeshSource for Parser<T> {
+    fn rewind(&mut self) -> Result<()> {
+        Parser::rewind(self)
+    }
+
+    fn next_triangle(&mut self) -> Result<Option<Triangle>, ParseError> {
+        Parser::next_triangle(self)
+    }
+
+    fn triangle_count_hint(&self) -> Option<u64> {
+        self.triangle_count_hint
+    }
+
+    fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    fn part_names(&self) -> &[String] {
+        Parser::part_names(self)
+    }
+
+    fn read_all(&mut self) -> Result<Mesh> {
+        Parser::read_all(self)
+    }
+
+    fn read_all_with_bounds(&mut self) -> Result<(Mesh, AABB)> {
+        Parser::read_all_with_bounds(self)
     }
 }
 
@@ -124,78 +523,287 @@ impl Parser<fs::File> {
     }
 }
 
-fn deduce_stl_type<T: BufRead + io::Seek>(reader: &mut T) -> Result<StlType> {
-    // skip header
-    reader.seek(SeekFrom::Start(HEADER_SIZE))?;
-
-    // the best way to distinguish between 'ascii' and 'bin' files is to check whether the
-    // specified triangle count matches the size of the file
-    let triangles = reader.read_u32::<LittleEndian>()? as u64;
+pub(crate) fn deduce_stl_type<T: BufRead + io::Seek>(reader: &mut T) -> Result<StlType> {
     let filesize = reader.seek(SeekFrom::End(0))?;
-    if triangles * TRIANGLE_SIZE + HEADER_SIZE + std::mem::size_of::<u32>() as u64 == filesize {
-        return Ok(StlType::Binary);
+    reader.seek(SeekFrom::Start(0))?;
+    let mut start = vec![];
+    reader.take(SNIFF_LENGTH).read_to_end(&mut start)?;
+
+    Ok(stl_type_of(&start, Some(filesize)).unwrap_or(StlType::Ascii))
+}
+
+// tells ascii from binary files by the first bytes of the file and its size if known,
+// None if neither gives the type away
+pub(crate) fn stl_type_of(start: &[u8], size: Option<u64>) -> Option<StlType> {
+    // many binary headers start with 'solid' as well, the size implied by their triangle count gives them away
+    if size.is_some() && size == binary_stl_size(start) {
+        return Some(StlType::Binary);
+    }
+
+    let is_solid = String::from_utf8_lossy(start)
+        .trim_start()
+        .get(..5)
+        .is_some_and(|s| s.eq_ignore_ascii_case("solid"));
+
+    // the name of the solid on the first line may contain anything
+    let text = match start.iter().position(|b| *b == b'\n') {
+        Some(i) => &start[i + 1..],
+        None => start,
+    };
+    let is_printable = text.iter().all(|b| !b.is_ascii_control() || b.is_ascii_whitespace());
+
+    if !is_solid || !is_printable {
+        return Some(StlType::Binary);
+    }
+
+    // only ascii files continue with facets on the next line
+    let keyword = String::from_utf8_lossy(text).trim_start().to_ascii_lowercase();
+    if text.len() < start.len() && (keyword.starts_with("facet") || keyword.starts_with("endsolid")) {
+        Some(StlType::Ascii)
+    } else {
+        None
+    }
+}
+
+// the size of a binary file as implied by the triangle count of its header
+pub(crate) fn binary_stl_size(header: &[u8]) -> Option<u64> {
+    let count = header.get(80..84)?;
+    Some(84 + 50 * u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as u64)
+}
+
+// properties of a binary file derived from its header and size
+pub(crate) struct BinaryLayout {
+    pub facet_colors: FacetColors,
+    pub triangles: u64,
+    pub warnings: Vec<String>,
+}
+
+impl BinaryLayout {
+    // the header includes the triangle count
+    pub fn new(header: &[u8], filesize: u64, recovery: Recovery) -> Result<Self, ParseError> {
+        if filesize < HEADER_SIZE + 4 {
+            return Err(ParseError::Truncated { triangles: 0 });
+        }
+
+        let declared = Self::declared_triangles(header)?;
+        let (triangles, mismatch) = binary_triangle_count(declared, filesize - HEADER_SIZE - 4);
+
+        let mut warnings = vec![];
+        match (mismatch, recovery) {
+            (Some(e), Recovery::Strict) => return Err(e),
+            (Some(e), Recovery::Lenient) => warnings.push(format!("{}, reading {} triangles", e, triangles)),
+            (None, _) => {}
+        }
+
+        Ok(Self {
+            facet_colors: facet_colors_from_header(&header[..HEADER_SIZE as usize]),
+            triangles,
+            warnings,
+        })
+    }
+
+    // the triangle count of the header
+    pub fn declared_triangles(header: &[u8]) -> Result<u64, ParseError> {
+        match header.get(HEADER_SIZE as usize..HEADER_SIZE as usize + 4) {
+            Some(count) => Ok(LittleEndian::read_u32(count) as u64),
+            None => Err(ParseError::Truncated { triangles: 0 }),
+        }
     }
+}
+
+// checks the declared triangle count against the size of the triangle data,
+// returns the number of triangles the file actually contains and the mismatch if any
+fn binary_triangle_count(declared: u64, data_size: u64) -> (u64, Option<ParseError>) {
+    let available = data_size / TRIANGLE_SIZE;
+    let trailing = data_size % TRIANGLE_SIZE;
+
+    let mismatch = if declared > available {
+        Some(ParseError::Truncated { triangles: available })
+    } else if declared < available {
+        Some(ParseError::BadTriangleCount {
+            header: declared,
+            actual: available,
+        })
+    } else if trailing > 0 {
+        Some(ParseError::TrailingBytes { bytes: trailing })
+    } else {
+        None
+    };
+
+    (available, mismatch)
+}
+
+// returns None at the end of the file, invalid UTF-8 is replaced and reported by the flag
+fn read_ascii_line<T: BufRead>(reader: &mut T) -> io::Result<Option<(String, bool)>> {
+    let mut line = Vec::new();
+    if reader.read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(match String::from_utf8(line) {
+        Ok(line) => (line, true),
+        Err(e) => (String::from_utf8_lossy(e.as_bytes()).into_owned(), false),
+    }))
+}
+
+// splits a line on whitespace and records the 1-based column of each token
+pub(crate) fn tokenize(line: &str) -> Vec<(usize, &str)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+
+    for (i, c) in line.char_indices().chain(std::iter::once((line.len(), ' '))) {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                tokens.push((s + 1, &line[s..i]));
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+
+    tokens
+}
+
+/// Parses the three numbers following the offset, the error holds the column and a message
+pub(crate) fn parse_vec3<S: AsRef<str>>(tokens: &[(usize, S)], offset: usize) -> Result<Vec3, (usize, String)> {
+    let mut v = [0.0f32; 3];
+    let end = tokens.last().map_or(1, |(c, t)| c + t.as_ref().len());
 
-    // Note: also malformed binary STL files get classified as 'ascii'
-    Ok(StlType::Ascii)
+    for (i, component) in v.iter_mut().enumerate() {
+        let (column, token) = match tokens.get(offset + i) {
+            Some((c, t)) => (*c, t.as_ref()),
+            None => return Err((end, "expected a number".to_string())),
+        };
+
+        *component = token
+            .parse::<f32>()
+            .map_err(|_| (column, format!("invalid number '{}'", token)))?;
+    }
+
+    Ok(Vec3::new(v[0], v[1], v[2]))
+}
+
+fn owned_tokens(line: &str) -> Vec<(usize, String)> {
+    tokenize(line).into_iter().map(|(c, t)| (c, t.to_string())).collect()
+}
+
+fn is_keyword(tokens: &[(usize, String)], keyword: &str) -> bool {
+    tokens
+        .first()
+        .is_some_and(|(_, token)| token.eq_ignore_ascii_case(keyword))
+}
+
+// the name is everything following the 'solid' keyword
+fn solid_name(tokens: &[(usize, String)]) -> String {
+    tokens
+        .iter()
+        .skip(1)
+        .map(|(_, token)| token.as_str())
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
-fn read_ascii_line<T: BufRead>(reader: &mut T) -> Result<String> {
-    let mut line = String::new();
-    reader.read_line(&mut line)?;
-    Ok(line.trim_start().to_ascii_lowercase())
+// like read_exact, but returns how many bytes have been read before the end of the file
+fn read_up_to<T: io::Read>(reader: &mut T, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
 }
 
-fn read_ascii_triangle<T: BufRead>(reader: &mut T) -> Result<Triangle> {
-    let mut vertices = [
-        Vec3::new(0.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 0.0),
-    ];
+fn read_triangle<T: io::Read>(reader: &mut T, facet_colors: FacetColors) -> io::Result<Triangle> {
+    let mut bytes = [0u8; TRIANGLE_SIZE as usize];
+    reader.read_exact(&mut bytes)?;
 
-    //let (nx, ny, nz) = scan_fmt!(&read_ascii_line(reader)?, "facet normal {f} {f} {f}", f32, f32, f32)?;
+    Ok(decode_triangle(&bytes, facet_colors))
+}
 
-    read_ascii_line(reader)?; // "outer loop"
+// decodes the normal, the vertices and the attributes of a binary triangle (50 bytes)
+pub(crate) fn decode_triangle(bytes: &[u8], facet_colors: FacetColors) -> Triangle {
+    let vec3 = |offset: usize| {
+        Vec3::new(
+            LittleEndian::read_f32(&bytes[offset..]),
+            LittleEndian::read_f32(&bytes[offset + 4..]),
+            LittleEndian::read_f32(&bytes[offset + 8..]),
+        )
+    };
 
-    /*for v in &mut vertices {
-        let (vx, vy, vz) = scan_fmt!(&read_ascii_line(reader)?, "vertex {f} {f} {f}", f32, f32, f32)?;
-        v.x = vx;
-        v.y = vy;
-        v.z = vz;
-    }*/
+    let attributes = LittleEndian::read_u16(&bytes[48..]);
 
-    read_ascii_line(reader)?; // "endloop"
-    read_ascii_line(reader)?; // "endfacet"
+    let mut triangle = Triangle::new([vec3(12), vec3(24), vec3(36)], vec3(0));
+    triangle.set_color(facet_color(attributes, facet_colors));
 
-    Ok(Triangle::new(vertices, Vec3::new(0.0, 0.0, 0.0)))
+    triangle
 }
 
-fn read_vec3<T: io::Read>(reader: &mut T) -> Result<Vec3> {
-    Ok(Vec3::new(
-        reader.read_f32::<LittleEndian>()?,
-        reader.read_f32::<LittleEndian>()?,
-        reader.read_f32::<LittleEndian>()?,
-    ))
+// rejects non-finite vertices and fixes missing normals
+pub(crate) fn check_triangle(triangle: &mut Triangle, index: u64, recalculate_normals: bool) -> Result<(), ParseError> {
+    if !triangle.has_finite_vertices() {
+        return Err(ParseError::NonFinite { triangle: index });
+    }
+
+    // calculate normal from vertices using right hand rule is case it is missing
+    if recalculate_normals
+        || triangle.normal == Vec3::new(0.0, 0.0, 0.0)
+        || triangle.normal.iter().any(|c| !c.is_finite())
+    {
+        triangle.recalculate_normal();
+    }
+
+    Ok(())
 }
 
-fn read_triangle<T: io::Read>(reader: &mut T) -> Result<Triangle> {
-    let n = read_vec3(reader)?;
-    let v1 = read_vec3(reader)?;
-    let v2 = read_vec3(reader)?;
-    let v3 = read_vec3(reader)?;
+// Materialise files are identified by 'COLOR=' or 'MATERIAL=' in the header
+fn facet_colors_from_header(header: &[u8]) -> FacetColors {
+    let find = |keyword: &[u8]| {
+        header
+            .windows(keyword.len())
+            .position(|w| w == keyword)
+            .map(|i| &header[i + keyword.len()..])
+    };
 
-    reader.read_u16::<LittleEndian>()?; // attributes
+    // 'COLOR=' is followed by the rgba default color, 'MATERIAL=' by the diffuse, specular and ambient colors
+    let rgb = |bytes: &[u8]| {
+        bytes
+            .get(..3)
+            .map(|c| Vec3::new(c[0] as f32, c[1] as f32, c[2] as f32) / 255.0)
+    };
 
-    Ok(Triangle::new([v1, v2, v3], n))
+    match (find(b"COLOR="), find(b"MATERIAL=")) {
+        (Some(color), _) => FacetColors::Materialise { default: rgb(color) },
+        (None, Some(material)) => FacetColors::Materialise { default: rgb(material) },
+        (None, None) => FacetColors::VisCam,
+    }
+}
+
+fn facet_color(attributes: u16, facet_colors: FacetColors) -> Option<Vec3> {
+    let channel = |shift: u16| ((attributes >> shift) & 0x1f) as f32 / 31.0;
+    let has_color = attributes & 0x8000 != 0;
+
+    match facet_colors {
+        FacetColors::VisCam if has_color => Some(Vec3::new(channel(10), channel(5), channel(0))),
+        FacetColors::VisCam => None,
+        FacetColors::Materialise { default } if has_color => default,
+        FacetColors::Materialise { .. } => Some(Vec3::new(channel(0), channel(5), channel(10))),
+    }
 }
 
 #[cfg(test)]
 mod test {
     use crate::mesh::*;
-    use crate::parser::Parser;
+    use crate::parser::{ParseError, Parser, Recovery};
+    use byteorder::{LittleEndian, WriteBytesExt};
     use std::io::Cursor;
 
-    const TRI_BIN: &'static [u8] = include_bytes!("test_models/triangle.stl");
+    const TRI_BIN: &[u8] = include_bytes!("test_models/triangle.stl");
+    const TRI_ASCII: &[u8] = include_bytes!("test_models/triangle_ascii.stl");
     #[test]
     fn parser_bin_test() {
         let reader = Cursor::new(TRI_BIN);
@@ -227,7 +835,193 @@ mod test {
                     Vec3::new(0.0, 1.0, 0.0)
                 ],
                 normal: Vec3::new(0.0, 0.0, 1.0),
+                part: 0,
+                attributes: None,
             }
         );
     }
+
+    #[test]
+    fn parser_ascii_test() {
+        let reader = Cursor::new(TRI_ASCII);
+        let mut parser = Parser::from_buf(reader, false).unwrap();
+        let mesh = parser.read_all().unwrap();
+
+        assert_eq!(mesh.len(), 2);
+        assert_eq!(mesh[0].normal, Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(mesh[0].vertices[0], Vec3::new(-1.0, -1.0, 0.0));
+        assert_eq!(mesh[0].vertices[1], Vec3::new(1.0, -1.0, 0.0));
+        assert_eq!(mesh[0].vertices[2], Vec3::new(0.0, 1.0, 0.0));
+
+        // missing normal gets recalculated
+        assert_eq!(mesh[1].normal, Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(mesh[1].vertices[1], Vec3::new(1.0, 0.0, 1.0));
+
+        assert_eq!(parser.triangle_count().unwrap(), 2);
+    }
+
+    #[test]
+    fn parser_ascii_error() {
+        let mut stl = String::from("solid test\n");
+        stl.push_str("facet normal 0 0 1\n");
+        stl.push_str("  outer loop\n");
+        stl.push_str("    vertex 0 0 0\n");
+        stl.push_str("    vertex 1 x 0\n");
+        stl.push_str("    vertex 0 1 0\n");
+        stl.push_str("  endloop\n");
+        stl.push_str("endfacet\n");
+        stl.push_str("endsolid test\n");
+
+        let mut parser = Parser::from_buf(Cursor::new(stl.as_bytes()), false).unwrap();
+        let err = match parser.next_triangle() {
+            Err(ParseError::Syntax(err)) => err,
+            result => panic!("expected a syntax error, got {:?}", result),
+        };
+
+        assert_eq!(err.line, 5);
+        assert_eq!(err.column, 14);
+        assert_eq!(err.to_string(), "line 5, column 14: invalid number 'x'");
+    }
+
+    #[test]
+    fn parser_ascii_unexpected_keyword() {
+        let mut stl = String::from("solid test\n");
+        stl.push_str("facet normal 0 0 1\n");
+        stl.push_str("  outer loop\n");
+        stl.push_str("    vertex 0 0 0\n");
+        stl.push_str("    vertex 1 0 0\n");
+        stl.push_str("  endloop\n");
+        stl.push_str("endfacet\n");
+        stl.push_str("endsolid test\n");
+
+        let mut parser = Parser::from_buf(Cursor::new(stl.as_bytes()), false).unwrap();
+        let err = match parser.next_triangle() {
+            Err(ParseError::Syntax(err)) => err,
+            result => panic!("expected a syntax error, got {:?}", result),
+        };
+
+        assert_eq!((err.line, err.column), (6, 3));
+        assert_eq!(err.message, "expected 'vertex', found 'endloop'");
+    }
+
+    #[test]
+    fn parser_ascii_parts() {
+        let mut stl = String::new();
+        for (name, z) in [("base plate", 0), ("lid", 1)].iter() {
+            stl.push_str(&format!("solid {}\n", name));
+            stl.push_str("facet normal 0 0 1\n outer loop\n");
+            stl.push_str(&format!("  vertex 0 0 {0}\n  vertex 1 0 {0}\n  vertex 0 1 {0}\n", z));
+            stl.push_str(" endloop\nendfacet\n");
+            stl.push_str(&format!("endsolid {}\n", name));
+        }
+
+        let mut parser = Parser::from_buf(Cursor::new(stl.as_bytes()), false).unwrap();
+        let parts = parser.read_parts().unwrap();
+
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name, "base plate");
+        assert_eq!(parts[1].name, "lid");
+        assert_eq!(parts[0].mesh.len(), 1);
+        assert_eq!(parts[1].mesh[0].part, 1);
+        assert_eq!(parts[1].mesh[0].vertices[0], Vec3::new(0.0, 0.0, 1.0));
+
+        // rewinding restarts at the first part
+        let mesh = parser.read_all().unwrap();
+        assert_eq!(mesh[0].part, 0);
+        assert_eq!(parser.part_names().len(), 2);
+    }
+
+    fn binary_stl(header: &[u8], attributes: &[u16]) -> Vec<u8> {
+        let mut stl = header.to_vec();
+        stl.resize(80, b' ');
+        stl.write_u32::<LittleEndian>(attributes.len() as u32).unwrap();
+
+        for a in attributes {
+            for v in [0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0].iter() {
+                stl.write_f32::<LittleEndian>(*v).unwrap();
+            }
+            stl.write_u16::<LittleEndian>(*a).unwrap();
+        }
+
+        stl
+    }
+
+    #[test]
+    fn parser_bin_viscam_colors() {
+        let stl = binary_stl(b"solidview", &[0x8000 | 31 << 10 | 16 << 5, 0x7fff]);
+        let mesh = Parser::from_buf(Cursor::new(stl), false).unwrap().read_all().unwrap();
+
+        assert_eq!(mesh[0].color(), Some(Vec3::new(1.0, 16.0 / 31.0, 0.0)));
+        assert_eq!(mesh[1].color(), None);
+    }
+
+    #[test]
+    fn parser_bin_materialise_colors() {
+        let stl = binary_stl(b"COLOR=\xff\x00\x00\xff MATERIAL=", &[31 << 10, 0x8000]);
+        let mesh = Parser::from_buf(Cursor::new(stl), false).unwrap().read_all().unwrap();
+
+        assert_eq!(mesh[0].color(), Some(Vec3::new(0.0, 0.0, 1.0)));
+        assert_eq!(mesh[1].color(), Some(Vec3::new(1.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn parser_bin_truncated() {
+        let mut stl = binary_stl(b"", &[0, 0]);
+        stl.truncate(stl.len() - 10);
+
+        // strict mode rejects the file upfront
+        let err = Parser::from_buf(Cursor::new(stl.clone()), false).err().unwrap();
+        match err.downcast_ref::<ParseError>() {
+            Some(ParseError::Truncated { triangles: 1 }) => {}
+            _ => panic!("expected a truncated file, got {:?}", err),
+        }
+
+        // lenient mode reads the complete triangles
+        let mut parser = Parser::with_recovery(Cursor::new(stl), false, Recovery::Lenient).unwrap();
+        assert_eq!(parser.read_all().unwrap().len(), 1);
+        assert_eq!(parser.warnings.len(), 1);
+    }
+
+    #[test]
+    fn parser_bin_bad_triangle_count() {
+        let mut stl = binary_stl(b"", &[0, 0, 0]);
+        stl[80] = 1;
+
+        let err = Parser::from_buf(Cursor::new(stl), false).err().unwrap();
+        match err.downcast_ref::<ParseError>() {
+            Some(ParseError::BadTriangleCount { header: 1, actual: 3 }) => {}
+            _ => panic!("expected a bad triangle count, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn parser_bin_lenient() {
+        // binary file with a header starting with 'solid', a zero triangle count and trailing bytes
+        let mut stl = binary_stl(b"solid exported by a buggy tool\n", &[0, 0]);
+        stl[80] = 0;
+        stl.extend_from_slice(b"\0\0\0");
+
+        let err = Parser::from_buf(Cursor::new(stl.clone()), false).err().unwrap();
+        assert_eq!(err.to_string(), "header declares 0 triangles but the file contains 2");
+
+        let mut parser = Parser::with_recovery(Cursor::new(stl), false, Recovery::Lenient).unwrap();
+        assert_eq!(parser.read_all().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn parser_non_finite_and_encoding() {
+        let mut stl = binary_stl(b"", &[0, 0]);
+        stl[84 + 50 + 12..84 + 50 + 16].copy_from_slice(&f32::NAN.to_le_bytes());
+
+        let mut parser = Parser::from_buf(Cursor::new(stl), false).unwrap();
+        assert!(parser.next_triangle().unwrap().is_some());
+        assert!(matches!(
+            parser.next_triangle(),
+            Err(ParseError::NonFinite { triangle: 1 })
+        ));
+
+        let stl = b"solid \xff\nfacet normal 0 0 1\nouter loop\nvertex \xff 0 0\n";
+        let mut parser = Parser::from_buf(Cursor::new(&stl[..]), false).unwrap();
+        assert!(matches!(parser.next_triangle(), Err(ParseError::Encoding { line: 4 })));
+    }
 }
@@ -1,5 +1,4 @@
 use std::convert::From;
-use std::i32;
 
 use crate::mesh::{Vec2, Vec4};
 use std::ops::{Add, Mul};
@@ -83,10 +82,10 @@ impl From<(u8, u8, u8, u8)> for RGBA {
 impl From<(f32, f32, f32, f32)> for RGBA {
     fn from(rgba: (f32, f32, f32, f32)) -> Self {
         Self {
-            r: (rgba.0.min(1.0).max(0.0) * 255.0) as u8,
-            g: (rgba.1.min(1.0).max(0.0) * 255.0) as u8,
-            b: (rgba.2.min(1.0).max(0.0) * 255.0) as u8,
-            a: (rgba.3.min(1.0).max(0.0) * 255.0) as u8,
+            r: (rgba.0.clamp(0.0, 1.0) * 255.0) as u8,
+            g: (rgba.1.clamp(0.0, 1.0) * 255.0) as u8,
+            b: (rgba.2.clamp(0.0, 1.0) * 255.0) as u8,
+            a: (rgba.3.clamp(0.0, 1.0) * 255.0) as u8,
         }
     }
 }
@@ -107,10 +106,10 @@ impl From<&str> for RGBA {
 impl From<&Vec4> for RGBA {
     fn from(vec: &Vec4) -> Self {
         Self {
-            r: (vec.x.min(1.0).max(0.0) * 255.0) as u8,
-            g: (vec.y.min(1.0).max(0.0) * 255.0) as u8,
-            b: (vec.z.min(1.0).max(0.0) * 255.0) as u8,
-            a: (vec.w.min(1.0).max(0.0) * 255.0) as u8,
+            r: (vec.x.clamp(0.0, 1.0) * 255.0) as u8,
+            g: (vec.y.clamp(0.0, 1.0) * 255.0) as u8,
+            b: (vec.z.clamp(0.0, 1.0) * 255.0) as u8,
+            a: (vec.w.clamp(0.0, 1.0) * 255.0) as u8,
         }
     }
 }
@@ -126,8 +125,7 @@ pub struct Picture {
 impl Picture {
     pub fn new(width: u32, height: u32) -> Self {
         let depth = 4;
-        let mut data = Vec::new();
-        data.resize((width * height * depth) as usize, 0);
+        let data = vec![0; (width * height * depth) as usize];
 
         let mut pic = Picture {
             data,
@@ -191,7 +189,7 @@ impl Picture {
 
         let dx = (x1 - x0).abs();
         let sx = if x0 < x1 { 1 } else { -1 };
-        let dy = -(y1 as i32 - y0 as i32).abs();
+        let dy = -(y1 - y0).abs();
         let sy = if y0 < y1 { 1 } else { -1 };
         let mut err = dx + dy;
         loop {
@@ -310,7 +308,7 @@ impl Picture {
     pub fn save(&self, path: &str) -> std::io::Result<()> {
         let file = std::fs::File::create(path)?;
         let buf = std::io::BufWriter::new(file);
-        let mut encoder = png::Encoder::new(buf, self.width as u32, self.height as u32);
+        let mut encoder = png::Encoder::new(buf, self.width, self.height);
 
         encoder.set_color(png::ColorType::RGBA);
         encoder.set_depth(png::BitDepth::Eight);
@@ -322,7 +320,7 @@ impl Picture {
     }
 
     pub fn stroke_string(&mut self, x: u32, y: u32, s: &str, char_size: f32, rgba: &RGBA) {
-        for (i, c) in s.chars().into_iter().enumerate() {
+        for (i, c) in s.chars().enumerate() {
             self.stroke_letter(x + i as u32 * (char_size * 0.7 + 6.0) as u32, y, c, char_size, rgba);
         }
     }
@@ -473,6 +471,7 @@ impl Picture {
     }
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
 
@@ -499,10 +498,11 @@ mod tests {
         pic.thick_line(256, 0, 256, 512, &(1.0, 0.0, 0.0, 1.0).into(), 1.0);
 
         // plot chars
-        let mut i = 0;
-        for c in ['0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'x', 'm'].iter() {
-            pic.stroke_letter(100 + i * 14, 100, *c, 10.0, &"000000FF".into());
-            i += 1;
+        for (i, c) in ['0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'x', 'm']
+            .iter()
+            .enumerate()
+        {
+            pic.stroke_letter(100 + i as u32 * 14, 100, *c, 10.0, &"000000FF".into());
         }
 
         pic.stroke_string(100, 200, "12x55mm", 10.0, &"E6E6E6FF".into());
@@ -0,0 +1,500 @@
+use crate::mesh::*;
+use crate::parser::ParseError;
+use anyhow::{anyhow, bail, Result};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::marker::PhantomData;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Encoding {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Result<Self> {
+        Ok(match name {
+            "char" | "int8" => ScalarType::I8,
+            "uchar" | "uint8" => ScalarType::U8,
+            "short" | "int16" => ScalarType::I16,
+            "ushort" | "uint16" => ScalarType::U16,
+            "int" | "int32" => ScalarType::I32,
+            "uint" | "uint32" => ScalarType::U32,
+            "float" | "float32" => ScalarType::F32,
+            "double" | "float64" => ScalarType::F64,
+            _ => bail!("PLY: unknown property type '{}'", name),
+        })
+    }
+
+    // colors are stored either as integers using the full range of the type or as floats in [0,1]
+    fn color_scale(&self) -> f32 {
+        match self {
+            ScalarType::I8 => 127.0,
+            ScalarType::U8 => 255.0,
+            ScalarType::I16 => 32767.0,
+            ScalarType::U16 => 65535.0,
+            ScalarType::I32 => 2147483647.0,
+            ScalarType::U32 => 4294967295.0,
+            ScalarType::F32 | ScalarType::F64 => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Property {
+    Scalar {
+        name: String,
+        ty: ScalarType,
+    },
+    List {
+        name: String,
+        count_ty: ScalarType,
+        item_ty: ScalarType,
+    },
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar { name, .. } => name,
+            Property::List { name, .. } => name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn property(&self, names: &[&str]) -> Option<usize> {
+        self.properties.iter().position(|p| names.contains(&p.name()))
+    }
+
+    fn scalar_type(&self, index: usize) -> Option<ScalarType> {
+        match self.properties[index] {
+            Property::Scalar { ty, .. } => Some(ty),
+            Property::List { .. } => None,
+        }
+    }
+}
+
+// reads the values of the body independent of the encoding
+trait ValueReader {
+    fn read(&mut self, ty: ScalarType) -> Result<f64>;
+}
+
+struct AsciiValues<R: BufRead> {
+    reader: R,
+    tokens: VecDeque<String>,
+}
+
+impl<R: BufRead> ValueReader for AsciiValues<R> {
+    fn read(&mut self, _ty: ScalarType) -> Result<f64> {
+        while self.tokens.is_empty() {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                bail!("PLY: unexpected end of file");
+            }
+            self.tokens.extend(line.split_whitespace().map(|t| t.to_string()));
+        }
+
+        let token = self.tokens.pop_front().unwrap_or_default();
+        token
+            .parse::<f64>()
+            .map_err(|_| anyhow!("PLY: invalid number '{}'", token))
+    }
+}
+
+struct BinaryValues<R: Read, B: ByteOrder> {
+    reader: R,
+    byte_order: PhantomData<B>,
+}
+
+impl<R: Read, B: ByteOrder> ValueReader for BinaryValues<R, B> {
+    fn read(&mut self, ty: ScalarType) -> Result<f64> {
+        let r = &mut self.reader;
+        Ok(match ty {
+            ScalarType::I8 => r.read_i8()? as f64,
+            ScalarType::U8 => r.read_u8()? as f64,
+            ScalarType::I16 => r.read_i16::<B>()? as f64,
+            ScalarType::U16 => r.read_u16::<B>()? as f64,
+            ScalarType::I32 => r.read_i32::<B>()? as f64,
+            ScalarType::U32 => r.read_u32::<B>()? as f64,
+            ScalarType::F32 => r.read_f32::<B>()? as f64,
+            ScalarType::F64 => r.read_f64::<B>()?,
+        })
+    }
+}
+
+/// Stanford PLY reader
+/// Supports all encodings, polygon faces and per vertex or per face colors.
+pub struct PlyParser;
+
+impl PlyParser {
+    pub fn from_buf<T: Read>(inner: T) -> Result<InMemorySource> {
+        let mut reader = BufReader::new(inner);
+        let (encoding, elements) = read_header(&mut reader)?;
+
+        let triangles = match encoding {
+            Encoding::Ascii => read_body(
+                &mut AsciiValues {
+                    reader,
+                    tokens: VecDeque::new(),
+                },
+                &elements,
+            )?,
+            Encoding::BinaryLittleEndian => read_body(
+                &mut BinaryValues::<_, LittleEndian> {
+                    reader,
+                    byte_order: PhantomData,
+                },
+                &elements,
+            )?,
+            Encoding::BinaryBigEndian => read_body(
+                &mut BinaryValues::<_, BigEndian> {
+                    reader,
+                    byte_order: PhantomData,
+                },
+                &elements,
+            )?,
+        };
+
+        Ok(InMemorySource::new(triangles, vec![]))
+    }
+}
+
+impl PlyParser {
+    pub fn from_file(filename: &str) -> Result<InMemorySource> {
+        let file = fs::File::open(filename)?;
+        Self::from_buf(file)
+    }
+}
+
+fn read_header<T: BufRead>(reader: &mut T) -> Result<(Encoding, Vec<Element>)> {
+    let mut lines = vec![];
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            bail!("PLY: missing 'end_header'");
+        }
+
+        let line = line.trim().to_string();
+        if line == "end_header" {
+            break;
+        }
+        lines.push(line);
+    }
+
+    if lines.first().map(|l| l.as_str()) != Some("ply") {
+        bail!("PLY: missing 'ply' magic");
+    }
+
+    let mut encoding = None;
+    let mut elements: Vec<Element> = vec![];
+
+    for line in &lines[1..] {
+        let tokens = line.split_whitespace().collect::<Vec<_>>();
+        match tokens.as_slice() {
+            ["format", format, _version] => {
+                encoding = Some(match *format {
+                    "ascii" => Encoding::Ascii,
+                    "binary_little_endian" => Encoding::BinaryLittleEndian,
+                    "binary_big_endian" => Encoding::BinaryBigEndian,
+                    _ => bail!("PLY: unknown format '{}'", format),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| anyhow!("PLY: invalid element count '{}'", count))?,
+                properties: vec![],
+            }),
+            ["property", "list", count_ty, item_ty, name] => elements
+                .last_mut()
+                .ok_or_else(|| anyhow!("PLY: property '{}' outside of an element", name))?
+                .properties
+                .push(Property::List {
+                    name: name.to_string(),
+                    count_ty: ScalarType::parse(count_ty)?,
+                    item_ty: ScalarType::parse(item_ty)?,
+                }),
+            ["property", ty, name] => elements
+                .last_mut()
+                .ok_or_else(|| anyhow!("PLY: property '{}' outside of an element", name))?
+                .properties
+                .push(Property::Scalar {
+                    name: name.to_string(),
+                    ty: ScalarType::parse(ty)?,
+                }),
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => bail!("PLY: invalid header line '{}'", line),
+        }
+    }
+
+    let encoding = encoding.ok_or_else(|| anyhow!("PLY: missing 'format'"))?;
+    Ok((encoding, elements))
+}
+
+fn read_body(values: &mut dyn ValueReader, elements: &[Element]) -> Result<Vec<Triangle>> {
+    let mut positions: Vec<Vec3> = vec![];
+    let mut colors: Vec<Vec3> = vec![];
+    let mut triangles = vec![];
+    let mut row = vec![];
+
+    for element in elements {
+        match element.name.as_str() {
+            "vertex" => {
+                let x = element.property(&["x"]);
+                let y = element.property(&["y"]);
+                let z = element.property(&["z"]);
+                let (x, y, z) = match (x, y, z) {
+                    (Some(x), Some(y), Some(z)) => (x, y, z),
+                    _ => bail!("PLY: vertex element requires 'x', 'y' and 'z'"),
+                };
+
+                let rgb = color_properties(element);
+
+                // the count is untrusted, don't reserve too much memory upfront
+                positions.reserve(element.count.min(1 << 20));
+
+                for _ in 0..element.count {
+                    read_row(values, element, &mut row)?;
+                    positions.push(Vec3::new(row[x][0] as f32, row[y][0] as f32, row[z][0] as f32));
+
+                    if let Some(rgb) = rgb {
+                        colors.push(color(element, &row, rgb));
+                    }
+                }
+            }
+            "face" => {
+                let indices = element
+                    .property(&["vertex_indices", "vertex_index"])
+                    .ok_or_else(|| anyhow!("PLY: face element requires 'vertex_indices'"))?;
+
+                let rgb = color_properties(element);
+
+                for _ in 0..element.count {
+                    read_row(values, element, &mut row)?;
+                    let polygon = row[indices]
+                        .iter()
+                        .map(|i| {
+                            // list items might be floats, casting them would saturate or truncate
+                            if !is_index(*i) {
+                                Err(anyhow!("PLY: invalid vertex index {}", i))
+                            } else if (*i as usize) < positions.len() {
+                                Ok(*i as usize)
+                            } else {
+                                Err(anyhow!("PLY: vertex index {} out of range", i))
+                            }
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+
+                    let face_color = rgb.map(|rgb| color(element, &row, rgb));
+
+                    // fan triangulation
+                    for i in 1..polygon.len().saturating_sub(1) {
+                        let corners = [polygon[0], polygon[i], polygon[i + 1]];
+                        let mut triangle = Triangle::new(
+                            [positions[corners[0]], positions[corners[1]], positions[corners[2]]],
+                            Vec3::new(0.0, 0.0, 0.0),
+                        );
+                        triangle.recalculate_normal();
+                        triangle.set_color(face_color);
+                        if !colors.is_empty() {
+                            triangle.set_vertex_colors(Some([
+                                colors[corners[0]],
+                                colors[corners[1]],
+                                colors[corners[2]],
+                            ]));
+                        }
+                        if !triangle.has_finite_vertices() {
+                            return Err(ParseError::NonFinite {
+                                triangle: triangles.len() as u64,
+                            }
+                            .into());
+                        }
+                        triangles.push(triangle);
+                    }
+                }
+            }
+            // skip unknown elements like edges or materials
+            _ => {
+                for _ in 0..element.count {
+                    read_row(values, element, &mut row)?;
+                }
+            }
+        }
+    }
+
+    Ok(triangles)
+}
+
+// reads all properties of a single element, scalars are stored as lists of length one
+// the row is reused between calls to avoid allocations
+fn read_row(values: &mut dyn ValueReader, element: &Element, row: &mut Vec<Vec<f64>>) -> Result<()> {
+    row.resize(element.properties.len(), vec![]);
+
+    for (property, values_out) in element.properties.iter().zip(row.iter_mut()) {
+        values_out.clear();
+        match property {
+            Property::Scalar { ty, .. } => values_out.push(values.read(*ty)?),
+            Property::List { count_ty, item_ty, .. } => {
+                let count = values.read(*count_ty)?;
+                if !is_index(count) {
+                    bail!("PLY: invalid list length {}", count);
+                }
+                for _ in 0..count as usize {
+                    values_out.push(values.read(*item_ty)?);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn is_index(value: f64) -> bool {
+    value >= 0.0 && value.fract() == 0.0
+}
+
+fn color_properties(element: &Element) -> Option<[usize; 3]> {
+    Some([
+        element.property(&["red", "r", "diffuse_red"])?,
+        element.property(&["green", "g", "diffuse_green"])?,
+        element.property(&["blue", "b", "diffuse_blue"])?,
+    ])
+}
+
+fn color(element: &Element, row: &[Vec<f64>], rgb: [usize; 3]) -> Vec3 {
+    let channel = |i: usize| {
+        let scale = element.scalar_type(i).map_or(1.0, |ty| ty.color_scale());
+        row[i].first().copied().unwrap_or_default() as f32 / scale
+    };
+
+    Vec3::new(channel(rgb[0]), channel(rgb[1]), channel(rgb[2]))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mesh::*;
+    use crate::parser::ParseError;
+    use crate::ply::PlyParser;
+    use byteorder::{BigEndian, WriteBytesExt};
+    use std::io::Cursor;
+
+    #[test]
+    fn ply_ascii() {
+        let ply = "ply\nformat ascii 1.0\ncomment quad\nelement vertex 4\n\
+                   property float x\nproperty float y\nproperty float z\n\
+                   property uchar red\nproperty uchar green\nproperty uchar blue\n\
+                   element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+                   0 0 0 255 0 0\n1 0 0 0 255 0\n1 1 0 0 0 255\n0 1 0 255 255 255\n4 0 1 2 3\n";
+
+        let mut parser = PlyParser::from_buf(Cursor::new(ply)).unwrap();
+        let mesh = parser.read_all().unwrap();
+
+        assert_eq!(mesh.len(), 2);
+        assert_eq!(mesh[0].normal, Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(mesh[1].vertices[2], Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(
+            mesh[1].vertex_colors(),
+            Some([
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec3::new(1.0, 1.0, 1.0)
+            ])
+        );
+    }
+
+    #[test]
+    fn ply_binary_big_endian() {
+        let mut ply = b"ply\nformat binary_big_endian 1.0\nelement vertex 3\n\
+                        property double x\nproperty double y\nproperty double z\n\
+                        element face 1\nproperty list uchar uint vertex_indices\n\
+                        property float red\nproperty float green\nproperty float blue\nend_header\n"
+            .to_vec();
+
+        for v in [[0.0, 0.0, 1.0], [1.0, 0.0, 1.0], [0.0, 1.0, 1.0]].iter() {
+            for c in v.iter() {
+                ply.write_f64::<BigEndian>(*c).unwrap();
+            }
+        }
+        ply.write_u8(3).unwrap();
+        for i in 0..3 {
+            ply.write_u32::<BigEndian>(i).unwrap();
+        }
+        for c in [0.5, 0.25, 1.0].iter() {
+            ply.write_f32::<BigEndian>(*c).unwrap();
+        }
+
+        let mut parser = PlyParser::from_buf(Cursor::new(ply)).unwrap();
+        let mesh = parser.read_all().unwrap();
+
+        assert_eq!(mesh.len(), 1);
+        assert_eq!(mesh[0].vertices[1], Vec3::new(1.0, 0.0, 1.0));
+        assert_eq!(mesh[0].color(), Some(Vec3::new(0.5, 0.25, 1.0)));
+        assert_eq!(mesh[0].vertex_colors(), None);
+    }
+
+    #[test]
+    fn ply_invalid_indices() {
+        let header = "ply\nformat ascii 1.0\nelement vertex 3\n\
+                      property float x\nproperty float y\nproperty float z\n\
+                      element face 1\nproperty list uchar float vertex_indices\nend_header\n\
+                      0 0 0\n1 0 0\n0 1 0\n";
+
+        for face in [
+            "3 0 1 2\n",
+            "3 0 1 -1\n",
+            "3 0 1 1.5\n",
+            "3 0 1 nan\n",
+            "3 0 1 inf\n",
+            "-1 0\n",
+        ] {
+            let ply = format!("{}{}", header, face);
+            let result = PlyParser::from_buf(Cursor::new(ply)).and_then(|mut p| p.read_all());
+            assert_eq!(result.is_ok(), face == "3 0 1 2\n", "{}", face);
+        }
+    }
+
+    #[test]
+    fn ply_truncated() {
+        let ply = "ply\nformat binary_little_endian 1.0\nelement vertex 3\n\
+                   property float x\nproperty float y\nproperty float z\nend_header\n\x00\x00";
+        assert!(PlyParser::from_buf(Cursor::new(ply)).is_err());
+    }
+
+    #[test]
+    fn ply_non_finite_vertex() {
+        let ply = "ply\nformat ascii 1.0\nelement vertex 3\n\
+                   property float x\nproperty float y\nproperty float z\n\
+                   element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+                   0 0 0\n1 nan 0\n0 1 inf\n3 0 1 2\n";
+        let err = PlyParser::from_buf(Cursor::new(ply)).err().unwrap();
+
+        assert!(matches!(
+            err.downcast_ref::<ParseError>(),
+            Some(ParseError::NonFinite { triangle: 0 })
+        ));
+    }
+}
@@ -0,0 +1,447 @@
+use crate::aabb::AABB;
+use crate::components::DisjointSets;
+use crate::hull::ConvexHull;
+use crate::loader::Format;
+use crate::mesh::*;
+use crate::transform::{Axis, Transform};
+use std::collections::HashMap;
+use std::f32::consts::{FRAC_1_SQRT_2, FRAC_PI_2};
+
+// the base of a model has to be at least this much larger than the ones along the other axes
+const BASE_DOMINANCE: f32 = 2.0;
+// hull faces whose normals differ less than this form a single resting face
+const COPLANAR_COS: f32 = 0.9999;
+// faces lying on the plate
+const CONTACT_COS: f32 = 0.99;
+// faces steeper than 45° towards the plate need supports when printing
+const OVERHANG_COS: f32 = FRAC_1_SQRT_2;
+// the resting faces with the largest support are scored against the whole model
+const MAX_CANDIDATES: usize = 32;
+// poses scoring this close to the best one are considered equally good
+const SCORE_TOLERANCE: f32 = 1e-3;
+
+// the up axis of a format without looking at the model, OBJ files are assumed to be Y-up (the
+// default of most exporters) and anything else without a convention Z-up
+fn default_up_axis(format: Option<Format>) -> Axis {
+    match format {
+        Some(Format::Obj) => Axis::Y,
+        _ => format.and_then(Format::up_axis).unwrap_or(Axis::Z),
+    }
+}
+
+/// Guesses the up axis of a model
+/// Formats prescribing an up axis (e.g. glTF) are trusted, otherwise the model is expected to rest
+/// on flat faces at the bottom of its bounding box. Without a clear base the default of the format is used.
+pub fn detect_up_axis(mesh: impl IntoIterator<Item = Triangle> + Copy, format: Option<Format>) -> Axis {
+    if let Some(axis) = format.and_then(Format::up_axis) {
+        return axis;
+    }
+
+    let fallback = default_up_axis(format);
+
+    let mut areas: Vec<(Axis, f32)> = [Axis::X, Axis::Y, Axis::Z]
+        .iter()
+        .zip(base_areas(mesh).iter())
+        .map(|(axis, area)| (*axis, *area))
+        .collect();
+    areas.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    match areas.as_slice() {
+        [(axis, base), (_, other), _] if *base > 0.0 && *base >= BASE_DOMINANCE * *other => *axis,
+        _ => fallback,
+    }
+}
+
+// area of the faces lying flat in the lower plane of the bounding box along each axis
+fn base_areas(mesh: impl IntoIterator<Item = Triangle> + Copy) -> [f32; 3] {
+    let aabb = AABB::from_iterable(mesh);
+    let size = aabb.size();
+    let tolerance = size.x.max(size.y).max(size.z) * 1e-3;
+
+    let mut areas = [0.0; 3];
+    for t in mesh {
+        let cross = (t.vertices[1] - t.vertices[0]).cross(&(t.vertices[2] - t.vertices[0]));
+        let area = cross.norm() * 0.5;
+        if area <= 0.0 {
+            continue;
+        }
+
+        for (axis, base) in areas.iter_mut().enumerate() {
+            // either winding, the orientation of the faces might be broken
+            let flat = cross[axis].abs() > 0.99 * cross.norm();
+            let bottom = t.vertices.iter().all(|v| v[axis] - aabb.lower[axis] <= tolerance);
+            if flat && bottom {
+                *base += area;
+            }
+        }
+    }
+
+    areas
+}
+
+/// A face of the convex hull the model can rest on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pose {
+    /// outward normal of the resting face, points towards the plate
+    pub down: Vec3,
+    /// area of the model touching the plate
+    pub contact_area: f32,
+    /// area facing the plate at more than 45°, needs supports when printing
+    pub overhang_area: f32,
+    /// angle the model can be tilted by before it tips over (radians)
+    pub stability: f32,
+    pub score: f32,
+}
+
+impl Pose {
+    /// Rotates the model onto its resting face
+    pub fn transform(&self) -> Transform {
+        Transform::alignment(self.down, Vec3::new(0.0, 0.0, -1.0))
+    }
+}
+
+/// Finds the faces of the convex hull the model rests on without tipping over, best first
+/// The poses are scored by their stability, contact area and overhang area, the areas relative to
+/// the surface of the model. Like the renderer the overhangs trust the winding of the triangles.
+pub fn rest_poses(mesh: impl IntoIterator<Item = Triangle> + Copy) -> Vec<Pose> {
+    let hull = match ConvexHull::from_mesh(mesh) {
+        Some(hull) => hull,
+        None => return vec![],
+    };
+    let center = center_of_mass(mesh);
+
+    let mut poses: Vec<(Pose, f32)> = supports(&hull)
+        .into_iter()
+        .filter_map(|support| {
+            let stability = support.stability(&hull, &center)?;
+            let pose = Pose {
+                down: support.normal,
+                contact_area: 0.0,
+                overhang_area: 0.0,
+                stability,
+                score: 0.0,
+            };
+            Some((pose, support.area))
+        })
+        .collect();
+    poses.sort_by(|a, b| b.1.total_cmp(&a.1));
+    poses.truncate(MAX_CANDIDATES);
+    let mut poses: Vec<Pose> = poses.into_iter().map(|(pose, _)| pose).collect();
+
+    // lowest point of the model along each candidate, i.e. the plate
+    let aabb = AABB::from_iterable(mesh);
+    let tolerance = glm::length(&aabb.size()) * 1e-3;
+    let plates: Vec<f32> = poses
+        .iter()
+        .map(|pose| hull.vertices.iter().fold(f32::MIN, |m, v| m.max(pose.down.dot(v))))
+        .collect();
+
+    let mut surface = 0.0;
+    for t in mesh {
+        let cross = (t.vertices[1] - t.vertices[0]).cross(&(t.vertices[2] - t.vertices[0]));
+        let area = cross.norm() * 0.5;
+        if area <= 0.0 {
+            continue;
+        }
+        surface += area;
+
+        let normal = cross.normalize();
+        for (pose, plate) in poses.iter_mut().zip(&plates) {
+            let facing = normal.dot(&pose.down);
+            if facing <= OVERHANG_COS {
+                continue;
+            }
+
+            let on_plate = t.vertices.iter().all(|v| plate - pose.down.dot(v) <= tolerance);
+            if on_plate && facing > CONTACT_COS {
+                pose.contact_area += area;
+            } else {
+                pose.overhang_area += area;
+            }
+        }
+    }
+
+    for pose in &mut poses {
+        pose.score = pose.stability / FRAC_PI_2 + (pose.contact_area - pose.overhang_area) / surface;
+    }
+    poses.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    poses
+}
+
+/// The best of the rest poses, the current one (resting on -z) wins ties
+pub fn best_pose(mesh: impl IntoIterator<Item = Triangle> + Copy) -> Option<Pose> {
+    let poses = rest_poses(mesh);
+    let best = *poses.first()?;
+
+    poses
+        .into_iter()
+        .find(|pose| -pose.down.z > COPLANAR_COS && pose.score >= best.score - SCORE_TOLERANCE)
+        .or(Some(best))
+}
+
+// coplanar faces of the convex hull
+struct Support {
+    normal: Vec3,
+    area: f32,
+    vertices: Vec<u32>,
+}
+
+impl Support {
+    // the angle the hull can be tilted by until the center of mass passes the border of the
+    // support, None if it tips over right away
+    fn stability(&self, hull: &ConvexHull, center: &Vec3) -> Option<f32> {
+        let plate = self
+            .vertices
+            .iter()
+            .fold(f32::MIN, |m, i| m.max(self.normal.dot(&hull.vertices[*i as usize])));
+        let height = plate - self.normal.dot(center);
+
+        // the support and the center of mass projected onto the plate
+        let other = if self.normal.x.abs() < 0.9 { Axis::X } else { Axis::Y };
+        let u = self.normal.cross(&other.unit()).normalize();
+        let v = self.normal.cross(&u);
+        let project = |p: &Vec3| Vec2::new(u.dot(p), v.dot(p));
+        let polygon = convex_polygon(
+            self.vertices
+                .iter()
+                .map(|i| project(&hull.vertices[*i as usize]))
+                .collect(),
+        );
+        let center = project(center);
+
+        // distance of the center to the closest edge, negative outside
+        let margin = (0..polygon.len())
+            .map(|i| {
+                let (a, b) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+                let edge = (b - a).normalize();
+                edge.x * (center.y - a.y) - edge.y * (center.x - a.x)
+            })
+            .fold(f32::MAX, f32::min);
+
+        if polygon.len() >= 3 && margin > 0.0 && height > 0.0 {
+            Some(margin.atan2(height))
+        } else {
+            None
+        }
+    }
+}
+
+// merges the faces of the hull lying in the same plane
+fn supports(hull: &ConvexHull) -> Vec<Support> {
+    let crosses: Vec<Vec3> = hull
+        .faces
+        .iter()
+        .map(|face| {
+            let [a, b, c] = hull.triangle(face);
+            (b - a).cross(&(c - a))
+        })
+        .collect();
+
+    let mut edges = HashMap::new();
+    for (i, face) in hull.faces.iter().enumerate() {
+        for j in 0..3 {
+            edges.insert((face[j], face[(j + 1) % 3]), i);
+        }
+    }
+
+    let mut sets = DisjointSets::new(hull.faces.len());
+    for (i, face) in hull.faces.iter().enumerate() {
+        for j in 0..3 {
+            if let Some(&neighbour) = edges.get(&(face[(j + 1) % 3], face[j])) {
+                if crosses[i].normalize().dot(&crosses[neighbour].normalize()) > COPLANAR_COS {
+                    sets.union(i as u32, neighbour as u32);
+                }
+            }
+        }
+    }
+
+    let mut supports: HashMap<u32, Support> = HashMap::new();
+    for (i, face) in hull.faces.iter().enumerate() {
+        let support = supports.entry(sets.find(i as u32)).or_insert_with(|| Support {
+            normal: Vec3::zeros(),
+            area: 0.0,
+            vertices: vec![],
+        });
+        support.normal += crosses[i];
+        support.area += crosses[i].norm() * 0.5;
+        support.vertices.extend(face);
+    }
+
+    let mut supports: Vec<Support> = supports.into_values().collect();
+    for support in &mut supports {
+        support.normal = support.normal.normalize();
+    }
+    // deterministic order for equally sized supports
+    supports.sort_by(|a, b| {
+        (a.normal.x.total_cmp(&b.normal.x))
+            .then(a.normal.y.total_cmp(&b.normal.y))
+            .then(a.normal.z.total_cmp(&b.normal.z))
+    });
+
+    supports
+}
+
+// monotone chain, counterclockwise
+fn convex_polygon(mut points: Vec<Vec2>) -> Vec<Vec2> {
+    points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    points.dedup();
+
+    // the last point of each chain starts the other one
+    let mut lower = half_polygon(points.iter());
+    let mut upper = half_polygon(points.iter().rev());
+    lower.pop();
+    upper.pop();
+    lower.append(&mut upper);
+
+    lower
+}
+
+fn half_polygon<'a>(points: impl Iterator<Item = &'a Vec2>) -> Vec<Vec2> {
+    let mut chain: Vec<Vec2> = vec![];
+    for p in points {
+        while let [.., o, a] = chain.as_slice() {
+            if (a.x - o.x) * (p.y - o.y) - (a.y - o.y) * (p.x - o.x) > 0.0 {
+                break;
+            }
+            chain.pop();
+        }
+        chain.push(*p);
+    }
+    chain
+}
+
+// center of the enclosed volume, of the surface if that's implausible (e.g. open or inverted models)
+fn center_of_mass(mesh: impl IntoIterator<Item = Triangle> + Copy) -> Vec3 {
+    let mut aabb = AABB::empty();
+    let mut volume = 0.0;
+    let mut weighted = glm::DVec3::zeros();
+    let mut area = 0.0;
+    let mut surface = glm::DVec3::zeros();
+
+    for t in mesh {
+        aabb.extend(&t);
+
+        let [a, b, c] = t.vertices.map(glm::convert::<Vec3, glm::DVec3>);
+        let tetrahedron = a.dot(&b.cross(&c)) / 6.0;
+        volume += tetrahedron;
+        weighted += (a + b + c) * tetrahedron / 4.0;
+
+        let triangle = (b - a).cross(&(c - a)).norm() * 0.5;
+        area += triangle;
+        surface += (a + b + c) * triangle / 3.0;
+    }
+
+    if volume > 0.0 {
+        let center: Vec3 = glm::convert(weighted / volume);
+        let inside = (0..3).all(|i| center[i] >= aabb.lower[i] && center[i] <= aabb.upper[i]);
+        if inside {
+            return center;
+        }
+    }
+
+    if area > 0.0 {
+        glm::convert(surface / area)
+    } else {
+        aabb.center()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::aabb::AABB;
+    use crate::loader::Format;
+    use crate::mesh::*;
+    use crate::pose::{best_pose, default_up_axis, detect_up_axis, rest_poses};
+    use crate::transform::{Axis, Transform};
+    use std::f32::consts::FRAC_PI_4;
+
+    // a box standing on a wider plate, the plate is at the bottom of the up axis
+    fn standing(up: usize) -> Mesh {
+        let mut plate = Vec3::new(10.0, 10.0, 10.0);
+        plate[up] = 1.0;
+        let mut tower = Vec3::new(2.0, 2.0, 2.0);
+        tower[up] = 20.0;
+
+        let mut mesh = cuboid(Vec3::zeros(), plate);
+        for t in &cuboid(Vec3::zeros(), tower) {
+            mesh.push(t);
+        }
+        mesh
+    }
+
+    #[test]
+    fn up_axis_from_geometry() {
+        assert_eq!(detect_up_axis(&standing(1), None), Axis::Y);
+        assert_eq!(detect_up_axis(&standing(2), Some(Format::Obj)), Axis::Z);
+        assert_eq!(detect_up_axis(&standing(0), Some(Format::Ply)), Axis::X);
+    }
+
+    #[test]
+    fn up_axis_fallback() {
+        // a cube rests on any of its faces
+        let cube = cuboid(Vec3::zeros(), Vec3::new(1.0, 1.0, 1.0));
+        assert_eq!(detect_up_axis(&cube, Some(Format::Obj)), Axis::Y);
+        assert_eq!(detect_up_axis(&cube, Some(Format::Off)), Axis::Z);
+        assert_eq!(detect_up_axis(&cube, None), Axis::Z);
+
+        // the specification wins
+        assert_eq!(detect_up_axis(&standing(2), Some(Format::Gltf)), Axis::Y);
+        assert_eq!(detect_up_axis(&standing(1), Some(Format::Stl)), Axis::Z);
+    }
+
+    #[test]
+    fn up_axis_by_format() {
+        // without looking at the geometry
+        assert_eq!(default_up_axis(Some(Format::Obj)), Axis::Y);
+        assert_eq!(default_up_axis(Some(Format::Gltf)), Axis::Y);
+        assert_eq!(default_up_axis(Some(Format::Ply)), Axis::Z);
+        assert_eq!(default_up_axis(None), Axis::Z);
+    }
+
+    #[test]
+    fn cube_poses() {
+        let cube = cuboid(Vec3::zeros(), Vec3::new(1.0, 1.0, 1.0));
+        let poses = rest_poses(&cube);
+
+        assert_eq!(poses.len(), 6);
+        for pose in &poses {
+            assert!((pose.stability - FRAC_PI_4).abs() < 1e-4);
+            assert!((pose.contact_area - 1.0).abs() < 1e-4);
+            assert_eq!(pose.overhang_area, 0.0);
+        }
+
+        // the current pose wins ties
+        assert!(best_pose(&cube).unwrap().transform().is_identity());
+    }
+
+    #[test]
+    fn lay_down_flat() {
+        // a slab standing on its narrow side
+        let mut slab = cuboid(Vec3::zeros(), Vec3::new(10.0, 1.0, 6.0));
+        let pose = best_pose(&slab).unwrap();
+        assert!(pose.down.y.abs() > 0.999);
+        assert!((pose.contact_area - 60.0).abs() < 1e-3);
+
+        pose.transform().apply_mesh(&mut slab);
+        assert!((AABB::from_mesh(&slab).size().z - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn avoid_overhangs() {
+        // upside down the plate overhangs
+        let mut mesh = standing(2);
+        Transform::rotation(Axis::X, 180.0).apply_mesh(&mut mesh);
+
+        let poses = rest_poses(&mesh);
+        let best = poses[0];
+        assert!((best.down - Vec3::new(0.0, 0.0, 1.0)).norm() < 1e-4);
+        // the plate and the bottom of the tower
+        assert!((best.contact_area - 104.0).abs() < 1e-3);
+        assert_eq!(best.overhang_area, 0.0);
+
+        // the tower stands in the corner of the plate, resting on its top tips over
+        assert!(poses.iter().all(|p| p.down.z > -0.999));
+        assert!(poses.iter().any(|p| p.overhang_area > 0.0));
+    }
+}
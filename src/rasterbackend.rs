@@ -1,8 +1,10 @@
 use crate::aabb::*;
+use crate::defects::Defects;
 use crate::mesh::*;
 use crate::picture::*;
 use crate::zbuffer::*;
 
+use anyhow::{bail, Result};
 use std::f32::consts::PI;
 use std::time::{Duration, Instant};
 
@@ -12,12 +14,23 @@ pub struct RenderOptions {
     pub light_pos: Vec3,
     pub light_color: Vec3,
     pub ambient_color: Vec3,
+    /// used for triangles without a color defined by the model file (e.g. STL facet colors)
     pub model_color: Vec3,
+    /// colors used for the individual parts of a model, overrides the colors defined by the model file
+    pub part_colors: Vec<Vec3>,
     pub grid_color: Vec3,
+    /// colors used to highlight the defects of a model
+    pub open_edge_color: Vec3,
+    pub non_manifold_edge_color: Vec3,
+    pub flipped_face_color: Vec3,
+    /// used for the back faces in two-sided mode
+    pub back_face_color: Vec3,
     pub background_color: Vec4,
     pub zoom: f32,
     pub grid_visible: bool,
     pub draw_size_hint: bool,
+    /// draws the back faces instead of culling them, models with inverted normals look solid
+    pub two_sided: bool,
 }
 
 impl Default for RenderOptions {
@@ -28,11 +41,61 @@ impl Default for RenderOptions {
             light_color: Vec3::new(0.6, 0.6, 0.6),
             ambient_color: Vec3::new(0.4, 0.4, 0.4),
             model_color: Vec3::new(0.0, 0.45, 1.0),
+            part_colors: vec![],
             grid_color: Vec3::new(0.1, 0.1, 0.1),
+            open_edge_color: Vec3::new(0.9, 0.1, 0.1),
+            non_manifold_edge_color: Vec3::new(1.0, 0.6, 0.0),
+            flipped_face_color: Vec3::new(0.9, 0.2, 0.8),
+            back_face_color: Vec3::new(0.95, 0.75, 0.1),
             background_color: Vec4::new(1.0, 1.0, 1.0, 0.0),
             grid_visible: true,
             zoom: 1.0,
             draw_size_hint: true,
+            two_sided: false,
+        }
+    }
+}
+
+impl RenderOptions {
+    /// A palette of easily distinguishable colors starting with the default model color
+    pub fn default_part_colors() -> Vec<Vec3> {
+        vec![
+            Vec3::new(0.0, 0.45, 1.0),
+            Vec3::new(1.0, 0.5, 0.0),
+            Vec3::new(0.2, 0.75, 0.2),
+            Vec3::new(0.9, 0.2, 0.2),
+            Vec3::new(0.6, 0.35, 0.8),
+            Vec3::new(0.95, 0.8, 0.1),
+            Vec3::new(0.1, 0.75, 0.75),
+            Vec3::new(0.9, 0.4, 0.7),
+        ]
+    }
+
+    /// Parses a comma separated list of colors in the format 'RRGGBB', e.g. '0073FF,FF8000'
+    pub fn parse_palette(palette: &str) -> Result<Vec<Vec3>> {
+        palette
+            .split(',')
+            .map(|color| {
+                let color = color.trim().trim_start_matches('#');
+                if color.len() != 6 || !color.chars().all(|c| c.is_ascii_hexdigit()) {
+                    bail!("invalid color '{}', expected format: 'RRGGBB'", color);
+                }
+
+                let channel = |i: usize| u8::from_str_radix(&color[i..i + 2], 16).unwrap_or_default() as f32 / 255.0;
+                Ok(Vec3::new(channel(0), channel(2), channel(4)))
+            })
+            .collect()
+    }
+
+    // color of a fragment given its barycentric coordinates
+    fn surface_color(&self, triangle: &Triangle, w0: f32, w1: f32, w2: f32) -> Vec3 {
+        if !self.part_colors.is_empty() {
+            return self.part_colors[triangle.part as usize % self.part_colors.len()];
+        }
+
+        match triangle.vertex_colors() {
+            Some(c) => w0 * c[0] + w1 * c[1] + w2 * c[2],
+            None => triangle.color().unwrap_or(self.model_color),
         }
     }
 }
@@ -75,10 +138,15 @@ impl RasterBackend {
 
     pub fn fit_mesh_scale(&self, mesh: impl IntoIterator<Item = Triangle> + Copy) -> (AABB, f32) {
         let aabb = AABB::from_iterable(mesh);
+        (aabb, self.fit_aabb_scale(&aabb))
+    }
+
+    /// Like fit_mesh_scale for models whose AABB is already known (e.g. computed while loading)
+    pub fn fit_aabb_scale(&self, aabb: &AABB) -> f32 {
         let vp = self.view_projection(1.0);
 
         // scale the model such that is fills the entire canvas
-        (aabb, scale_for_unitsize(&vp, &aabb))
+        scale_for_unitsize(&vp, aabb)
     }
 
     pub fn render(
@@ -97,12 +165,7 @@ impl RasterBackend {
         pic.fill(&(&self.render_options.background_color).into());
 
         let vp = self.view_projection(self.render_options.zoom);
-
-        // calculate transforms taking the new model scale into account
-        let model = Mat4::identity()
-            .append_translation(&-aabb.center())
-            .append_scaling(model_scale);
-        let mvp = vp * model;
+        let (model, mvp) = self.model_transforms(model_scale, aabb);
 
         // let the AABB match the transformed model
         scaled_aabb.apply_transform(&model);
@@ -144,7 +207,7 @@ impl RasterBackend {
             let normal = -t.normal;
 
             // backface culling
-            if glm::dot(&eye_normal, &normal) < 0.0 {
+            if !self.render_options.two_sided && glm::dot(&eye_normal, &normal) < 0.0 {
                 continue;
             }
 
@@ -165,10 +228,21 @@ impl RasterBackend {
             let max_y = v0.y.max(v1.y).max(v2.y);
 
             // triangle bounding box in screen space
-            let smin_x = 0.max(((min_x + 1.0) / 2.0 * pic.width() as f32) as u32);
-            let smin_y = 0.max(((min_y + 1.0) / 2.0 * pic.height() as f32) as u32);
-            let smax_x = 0.max(pic.width().min(((max_x + 1.0) / 2.0 * pic.width() as f32) as u32));
-            let smax_y = 0.max(pic.height().min(((max_y + 1.0) / 2.0 * pic.height() as f32) as u32));
+            let smin_x = ((min_x + 1.0) / 2.0 * pic.width() as f32) as u32;
+            let smin_y = ((min_y + 1.0) / 2.0 * pic.height() as f32) as u32;
+            let smax_x = pic.width().min(((max_x + 1.0) / 2.0 * pic.width() as f32) as u32);
+            let smax_y = pic.height().min(((max_y + 1.0) / 2.0 * pic.height() as f32) as u32);
+
+            let p0 = v0.xy();
+            let p1 = v1.xy();
+            let p2 = v2.xy();
+
+            // triangles wound clockwise on screen are seen from behind
+            let area = edge_fn(&p0, &p1, &p2);
+            let back_face = area > 0.0;
+            if back_face && !self.render_options.two_sided {
+                continue;
+            }
 
             for y in smin_y..=smax_y {
                 for x in smin_x..=smax_x {
@@ -177,16 +251,16 @@ impl RasterBackend {
                     let ny = 2.0 * ((y as f32 / pic.height() as f32) - 0.5);
 
                     let p = Vec2::new(nx, ny);
-                    let p0 = v0.xy();
-                    let p1 = v1.xy();
-                    let p2 = v2.xy();
 
-                    let inside =
-                        edge_fn(&p, &p0, &p1) <= 0.0 && edge_fn(&p, &p1, &p2) <= 0.0 && edge_fn(&p, &p2, &p0) <= 0.0;
+                    let edges = [edge_fn(&p, &p0, &p1), edge_fn(&p, &p1, &p2), edge_fn(&p, &p2, &p0)];
+                    let inside = if back_face {
+                        edges.iter().all(|e| *e >= 0.0)
+                    } else {
+                        edges.iter().all(|e| *e <= 0.0)
+                    };
 
                     if inside {
                         // calculate barycentric coordinates
-                        let area = edge_fn(&p0, &p1, &p2);
                         let w0 = edge_fn(&p1, &p2, &p) / area;
                         let w1 = edge_fn(&p2, &p0, &p) / area;
                         let w2 = edge_fn(&p0, &p1, &p) / area;
@@ -209,6 +283,17 @@ impl RasterBackend {
 
                         if zbuf.test_and_set(x, y, frag_pos.z) {
                             // calculate lightning
+                            // interpolated vertex normals for smooth shading
+                            let mut normal = match t.vertex_normals() {
+                                Some(n) => -(w0 * n[0] + w1 * n[1] + w2 * n[2]).normalize(),
+                                None => normal,
+                            };
+
+                            // two-sided lighting, the normal faces the camera
+                            if self.render_options.two_sided && glm::dot(&eye_normal, &normal) < 0.0 {
+                                normal = -normal;
+                            }
+
                             let light_normal = (self.render_options.light_pos - fp).normalize(); // normal frag pos to light (world space)
                             let view_normal = (self.render_options.view_pos - fp).normalize(); // normal frag pos to view (world space)
                             let reflect_dir = glm::reflect_vec(&-light_normal, &normal);
@@ -222,10 +307,15 @@ impl RasterBackend {
                                 * self.render_options.light_color;
 
                             // merge
+                            let model_color = if back_face {
+                                self.render_options.back_face_color
+                            } else {
+                                self.render_options.surface_color(&t, w0, w1, w2)
+                            };
                             let mut color = self.render_options.ambient_color + diff_color + spec_color;
-                            color.x *= self.render_options.model_color.x;
-                            color.y *= self.render_options.model_color.y;
-                            color.z *= self.render_options.model_color.z;
+                            color.x *= model_color.x;
+                            color.y *= model_color.y;
+                            color.z *= model_color.z;
 
                             pic.set(x, y, &(color.x, color.y, color.z, 1.0).into());
                         }
@@ -268,20 +358,61 @@ impl RasterBackend {
     }
 }
 
+impl RasterBackend {
+    // model and model view projection matrix taking the model scale into account
+    fn model_transforms(&self, model_scale: f32, aabb: &AABB) -> (Mat4, Mat4) {
+        let model = Mat4::identity()
+            .append_translation(&-aabb.center())
+            .append_scaling(model_scale);
+
+        (model, self.view_projection(self.render_options.zoom) * model)
+    }
+
+    /// Draws the open and non-manifold edges on top of a picture rendered with the same parameters
+    /// The edges are drawn regardless of being hidden by the model, the flipped faces are tinted
+    /// beforehand (see Defects::tint).
+    pub fn draw_defects(&self, pic: &mut Picture, defects: &Defects, model_scale: f32, aabb: &AABB) {
+        let (_, mvp) = self.model_transforms(model_scale, aabb);
+
+        let edges = [
+            (&defects.open_edges, self.render_options.open_edge_color),
+            (&defects.non_manifold_edges, self.render_options.non_manifold_edge_color),
+        ];
+
+        for (lines, color) in &edges {
+            let rgba = (color.x, color.y, color.z, 1.0).into();
+            for [p0, p1] in lines.iter() {
+                // to screen space
+                let sp0 = matmul(&mvp, p0).xy();
+                let sp1 = matmul(&mvp, p1).xy();
+
+                pic.thick_line(
+                    ((sp0.x + 1.0) / 2.0 * pic.width() as f32) as i32,
+                    ((sp0.y + 1.0) / 2.0 * pic.height() as f32) as i32,
+                    ((sp1.x + 1.0) / 2.0 * pic.width() as f32) as i32,
+                    ((sp1.y + 1.0) / 2.0 * pic.height() as f32) as i32,
+                    &rgba,
+                    2.0,
+                );
+            }
+        }
+    }
+}
+
 fn edge_fn(a: &Vec2, b: &Vec2, c: &Vec2) -> f32 {
     (c.x - a.x) * (b.y - a.y) - (c.y - a.y) * (b.x - a.x)
 }
 
 fn scale_for_unitsize(mvp: &Mat4, aabb: &AABB) -> f32 {
     let edges = [
-        matmul(&mvp, &Vec3::new(aabb.lower.x, aabb.lower.y, aabb.lower.z)),
-        matmul(&mvp, &Vec3::new(aabb.upper.x, aabb.lower.y, aabb.lower.z)),
-        matmul(&mvp, &Vec3::new(aabb.lower.x, aabb.upper.y, aabb.lower.z)),
-        matmul(&mvp, &Vec3::new(aabb.upper.x, aabb.upper.y, aabb.lower.z)),
-        matmul(&mvp, &Vec3::new(aabb.lower.x, aabb.lower.y, aabb.upper.z)),
-        matmul(&mvp, &Vec3::new(aabb.upper.x, aabb.lower.y, aabb.upper.z)),
-        matmul(&mvp, &Vec3::new(aabb.lower.x, aabb.upper.y, aabb.upper.z)),
-        matmul(&mvp, &Vec3::new(aabb.upper.x, aabb.upper.y, aabb.upper.z)),
+        matmul(mvp, &Vec3::new(aabb.lower.x, aabb.lower.y, aabb.lower.z)),
+        matmul(mvp, &Vec3::new(aabb.upper.x, aabb.lower.y, aabb.lower.z)),
+        matmul(mvp, &Vec3::new(aabb.lower.x, aabb.upper.y, aabb.lower.z)),
+        matmul(mvp, &Vec3::new(aabb.upper.x, aabb.upper.y, aabb.lower.z)),
+        matmul(mvp, &Vec3::new(aabb.lower.x, aabb.lower.y, aabb.upper.z)),
+        matmul(mvp, &Vec3::new(aabb.upper.x, aabb.lower.y, aabb.upper.z)),
+        matmul(mvp, &Vec3::new(aabb.lower.x, aabb.upper.y, aabb.upper.z)),
+        matmul(mvp, &Vec3::new(aabb.upper.x, aabb.upper.y, aabb.upper.z)),
     ];
 
     let mut min = Vec3::new(f32::MAX, f32::MAX, f32::MAX);
@@ -304,7 +435,7 @@ fn draw_grid(pic: &mut Picture, vp: &Mat4, z: f32, color: &Vec3, model_size: Vec
     let grid_color = (color.x, color.y, color.z, 1.0).into();
     let grid_size = 10.0; // mm
     let grid_count = ((max_xy * scale) / scale / grid_size + 1.0) as i32;
-    let grid_spacing = grid_size * scale as f32;
+    let grid_spacing = grid_size * scale;
 
     let ox = grid_count as f32 * grid_spacing / 2.0;
 
@@ -313,8 +444,8 @@ fn draw_grid(pic: &mut Picture, vp: &Mat4, z: f32, color: &Vec3, model_size: Vec
         let p1 = Vec3::new(p0.x, -grid_count as f32 * grid_spacing * 0.5, z);
 
         // to screen space
-        let sp0 = matmul(&vp, &p0).xy();
-        let sp1 = matmul(&vp, &p1).xy();
+        let sp0 = matmul(vp, &p0).xy();
+        let sp1 = matmul(vp, &p1).xy();
 
         pic.thick_line(
             ((sp0.x + 1.0) / 2.0 * pic.width() as f32) as i32,
@@ -326,3 +457,58 @@ fn draw_grid(pic: &mut Picture, vp: &Mat4, z: f32, color: &Vec3, model_size: Vec
         );
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::aabb::AABB;
+    use crate::mesh::*;
+    use crate::orientation::orient;
+    use crate::picture::Picture;
+    use crate::rasterbackend::RasterBackend;
+
+    // an open square wound counterclockwise seen from above, far below the origin
+    fn plate() -> Mesh {
+        let corners = [
+            Vec3::new(-1.0, -1.0, -5.0),
+            Vec3::new(1.0, -1.0, -5.0),
+            Vec3::new(1.0, 1.0, -5.0),
+            Vec3::new(-1.0, 1.0, -5.0),
+        ];
+        let up = Vec3::new(0.0, 0.0, 1.0);
+        Mesh::new(vec![
+            Triangle::new([corners[0], corners[1], corners[2]], up),
+            Triangle::new([corners[0], corners[2], corners[3]], up),
+        ])
+    }
+
+    fn render(mesh: &Mesh, two_sided: bool) -> Picture {
+        let mut backend = RasterBackend::new(64, 64);
+        backend.render_options.grid_visible = false;
+        backend.render_options.draw_size_hint = false;
+        backend.render_options.two_sided = two_sided;
+
+        let aabb = AABB::from_mesh(mesh);
+        let scale = backend.fit_aabb_scale(&aabb);
+        backend.render(mesh, scale, &aabb, None)
+    }
+
+    #[test]
+    fn two_sided_open_mesh() {
+        let mut mesh = plate();
+        assert!(orient(&mut mesh).is_empty());
+
+        // seen from above, the front faces look the same in both modes
+        let front = render(&mesh, false).get(32, 32);
+        assert_ne!(front.a, 0);
+        assert_eq!(render(&mesh, true).get(32, 32), front);
+
+        // seen from below, the back faces are culled or drawn in their own color
+        for triangle in mesh.iter_mut() {
+            triangle.flip();
+        }
+        assert_eq!(render(&mesh, false).get(32, 32).a, 0);
+        let back = render(&mesh, true).get(32, 32);
+        assert_ne!(back.a, 0);
+        assert_ne!(back, front);
+    }
+}
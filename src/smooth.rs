@@ -0,0 +1,116 @@
+use crate::indexed::IndexedMesh;
+use crate::mesh::*;
+
+/// Angle in degrees between adjacent faces above which their edge is kept sharp by default
+pub const DEFAULT_CREASE_ANGLE: f32 = 30.0;
+
+impl IndexedMesh {
+    /// Normals of the corners of every face for smooth shading
+    /// A corner averages the normals of the faces around its vertex (weighted by their area),
+    /// faces whose angle to the face of the corner exceeds the crease angle (degrees) are left out.
+    pub fn vertex_normals(&self, crease_angle: f32) -> Vec<[Vec3; 3]> {
+        let min_cos = crease_angle.to_radians().cos();
+
+        // normal scaled by the area of the face
+        let weighted: Vec<Vec3> = self
+            .faces
+            .iter()
+            .map(|f| {
+                let v = f.indices.map(|i| self.vertices[i as usize]);
+                f.normal.normalize() * (v[1] - v[0]).cross(&(v[2] - v[0])).norm() * 0.5
+            })
+            .collect();
+
+        let vertex_faces = self.vertex_faces();
+
+        self.faces
+            .iter()
+            .map(|face| {
+                face.indices.map(|i| {
+                    let mut sum = Vec3::new(0.0, 0.0, 0.0);
+                    for other in &vertex_faces[i as usize] {
+                        if glm::dot(&face.normal.normalize(), &self.faces[*other].normal.normalize()) >= min_cos {
+                            sum += weighted[*other];
+                        }
+                    }
+
+                    // e.g. a corner of degenerate faces only
+                    let normal = sum.normalize();
+                    if normal.iter().all(|c| c.is_finite()) {
+                        normal
+                    } else {
+                        face.normal
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+/// Assigns smooth per vertex normals to the triangles of the mesh, edges steeper than
+/// the crease angle (degrees) stay sharp
+pub fn smooth_normals(mesh: &mut Mesh, crease_angle: f32) {
+    let indexed = IndexedMesh::welded(mesh);
+    let normals = indexed.vertex_normals(crease_angle);
+
+    for (triangle, normals) in mesh.iter_mut().zip(normals) {
+        triangle.set_vertex_normals(Some(normals));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mesh::*;
+    use crate::smooth::{smooth_normals, DEFAULT_CREASE_ANGLE};
+
+    // two faces meeting at the x axis with the given angle between their normals
+    fn hinge(angle: f32) -> Mesh {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let mut flat = Triangle::new(
+            [
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ],
+            Vec3::new(0.0, 0.0, 0.0),
+        );
+        flat.recalculate_normal();
+        let mut bent = Triangle::new(
+            [
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, -cos, sin),
+            ],
+            Vec3::new(0.0, 0.0, 0.0),
+        );
+        bent.recalculate_normal();
+
+        Mesh::new(vec![flat, bent])
+    }
+
+    #[test]
+    fn smooth_shallow_edges() {
+        let mut mesh = hinge(20.0);
+        smooth_normals(&mut mesh, DEFAULT_CREASE_ANGLE);
+
+        // the shared vertices get the average of both faces
+        let normals = mesh[0].vertex_normals().unwrap();
+        let average = (mesh[0].normal + mesh[1].normal).normalize();
+        assert!(glm::distance(&normals[0], &average) < 1e-5);
+        assert!(glm::distance(&normals[1], &average) < 1e-5);
+        assert_eq!(normals[2], mesh[0].normal);
+    }
+
+    #[test]
+    fn keep_creases_sharp() {
+        let mut mesh = hinge(90.0);
+        smooth_normals(&mut mesh, DEFAULT_CREASE_ANGLE);
+
+        assert_eq!(mesh[0].vertex_normals().unwrap(), [mesh[0].normal; 3]);
+        assert_eq!(mesh[1].vertex_normals().unwrap(), [mesh[1].normal; 3]);
+
+        // everything is smooth with a crease angle of 180°
+        smooth_normals(&mut mesh, 180.0);
+        assert_ne!(mesh[0].vertex_normals().unwrap()[0], mesh[0].normal);
+    }
+}
@@ -0,0 +1,196 @@
+use crate::aabb::AABB;
+use crate::indexed::IndexedMesh;
+use crate::mesh::*;
+use serde_json::{json, Value};
+use std::fmt;
+
+/// Measurements of a mesh
+/// The volume is signed, it's negative if the triangles are wound clockwise (inside out).
+/// Volume and centroid are only meaningful for watertight meshes.
+#[derive(Debug, Clone, Copy)]
+pub struct Stats {
+    pub triangles: usize,
+    /// vertices after welding coincident ones
+    pub vertices: usize,
+    pub volume: f32,
+    pub area: f32,
+    /// center of mass of the enclosed volume, center of the surface if the volume is zero
+    pub centroid: Vec3,
+    pub aabb: AABB,
+    /// edges with a single adjacent triangle
+    pub open_edges: usize,
+    /// edges shared by more than two triangles
+    pub non_manifold_edges: usize,
+    /// triangles with coincident vertices
+    pub degenerate_triangles: usize,
+}
+
+impl Stats {
+    pub fn from_mesh(mesh: &Mesh) -> Self {
+        Self::from_indexed(&IndexedMesh::welded(mesh))
+    }
+
+    pub fn from_indexed(mesh: &IndexedMesh) -> Self {
+        let mut aabb = AABB::empty();
+
+        // accumulate in double precision, large meshes sum up millions of tiny values
+        let mut volume = 0.0f64;
+        let mut area = 0.0f64;
+        let mut volume_centroid = glm::DVec3::zeros();
+        let mut area_centroid = glm::DVec3::zeros();
+
+        for face in &mesh.faces {
+            let triangle = mesh.triangle(face);
+            aabb.extend(&triangle);
+
+            let [v0, v1, v2] = triangle.vertices.map(glm::convert::<Vec3, glm::DVec3>);
+            let center = (v0 + v1 + v2) / 3.0;
+
+            // signed volume of the tetrahedron spanned by the triangle and the origin
+            let tetrahedron = v0.dot(&v1.cross(&v2)) / 6.0;
+            volume += tetrahedron;
+            volume_centroid += (v0 + v1 + v2) / 4.0 * tetrahedron;
+
+            let triangle_area = (v1 - v0).cross(&(v2 - v0)).norm() * 0.5;
+            area += triangle_area;
+            area_centroid += center * triangle_area;
+        }
+
+        let centroid = if volume != 0.0 {
+            volume_centroid / volume
+        } else if area != 0.0 {
+            area_centroid / area
+        } else {
+            glm::DVec3::zeros()
+        };
+
+        let mut open_edges = 0;
+        let mut non_manifold_edges = 0;
+        for faces in mesh.edge_faces().values() {
+            match faces.len() {
+                1 => open_edges += 1,
+                2 => {}
+                _ => non_manifold_edges += 1,
+            }
+        }
+
+        Self {
+            triangles: mesh.faces.len(),
+            vertices: mesh.vertices.len(),
+            volume: volume as f32,
+            area: area as f32,
+            centroid: glm::convert(centroid),
+            aabb,
+            open_edges,
+            non_manifold_edges,
+            degenerate_triangles: mesh.faces.iter().filter(|f| f.is_degenerate()).count(),
+        }
+    }
+
+    /// Closed surface without open or non-manifold edges
+    pub fn is_watertight(&self) -> bool {
+        self.triangles > 0 && self.open_edges == 0 && self.non_manifold_edges == 0
+    }
+
+    pub fn to_json(&self) -> Value {
+        let vec3 = |v: &Vec3| json!([v.x, v.y, v.z]);
+        let aabb = if self.triangles > 0 {
+            json!({
+                "min": vec3(&self.aabb.lower),
+                "max": vec3(&self.aabb.upper),
+                "size": vec3(&self.aabb.size()),
+            })
+        } else {
+            Value::Null
+        };
+
+        json!({
+            "triangles": self.triangles,
+            "vertices": self.vertices,
+            "volume": self.volume,
+            "surface_area": self.area,
+            "centroid": vec3(&self.centroid),
+            "bounding_box": aabb,
+            "open_edges": self.open_edges,
+            "non_manifold_edges": self.non_manifold_edges,
+            "degenerate_triangles": self.degenerate_triangles,
+            "watertight": self.is_watertight(),
+        })
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let vec3 = |v: &Vec3| format!("{:.3} {:.3} {:.3}", v.x, v.y, v.z);
+
+        writeln!(f, "Triangles             {}", self.triangles)?;
+        writeln!(f, "Vertices              {}", self.vertices)?;
+        writeln!(f, "Volume                {:.3}", self.volume)?;
+        writeln!(f, "Surface area          {:.3}", self.area)?;
+        writeln!(f, "Centroid              {}", vec3(&self.centroid))?;
+        if self.triangles > 0 {
+            writeln!(f, "Bounding box min      {}", vec3(&self.aabb.lower))?;
+            writeln!(f, "Bounding box max      {}", vec3(&self.aabb.upper))?;
+            writeln!(f, "Size                  {}", vec3(&self.aabb.size()))?;
+        }
+        writeln!(f, "Open edges            {}", self.open_edges)?;
+        writeln!(f, "Non-manifold edges    {}", self.non_manifold_edges)?;
+        writeln!(f, "Degenerate triangles  {}", self.degenerate_triangles)?;
+        write!(
+            f,
+            "Watertight            {}",
+            if self.is_watertight() { "yes" } else { "no" }
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mesh::*;
+    use crate::stats::Stats;
+
+    #[test]
+    fn stats_cuboid() {
+        let stats = Stats::from_mesh(&cuboid(Vec3::zeros(), Vec3::new(1.0, 2.0, 3.0)));
+
+        assert_eq!((stats.triangles, stats.vertices), (12, 8));
+        assert!((stats.volume - 6.0).abs() < 1e-5);
+        assert!((stats.area - 22.0).abs() < 1e-5);
+        assert!(glm::distance(&stats.centroid, &Vec3::new(0.5, 1.0, 1.5)) < 1e-5);
+        assert_eq!(stats.aabb.size(), Vec3::new(1.0, 2.0, 3.0));
+        assert!(stats.is_watertight());
+
+        let json = stats.to_json();
+        assert_eq!(json["triangles"], 12);
+        assert_eq!(json["watertight"], true);
+        assert_eq!(json["bounding_box"]["max"][2], 3.0);
+    }
+
+    #[test]
+    fn stats_open_and_non_manifold() {
+        let mut mesh = cuboid(Vec3::zeros(), Vec3::new(1.0, 1.0, 1.0));
+
+        // a fin sharing an edge with two faces of the cube
+        let mut fin = mesh[0].clone();
+        fin.vertices[2] = Vec3::new(-1.0, -1.0, 0.0);
+        mesh.push(fin);
+
+        let stats = Stats::from_mesh(&mesh);
+        assert_eq!(stats.non_manifold_edges, 1);
+        assert_eq!(stats.open_edges, 2);
+        assert!(!stats.is_watertight());
+    }
+
+    #[test]
+    fn stats_inside_out() {
+        let mut mesh = cuboid(Vec3::zeros(), Vec3::new(2.0, 2.0, 2.0));
+        for triangle in mesh.iter_mut() {
+            triangle.vertices.swap(1, 2);
+        }
+
+        let stats = Stats::from_mesh(&mesh);
+        assert!((stats.volume + 8.0).abs() < 1e-5);
+        // the centroid doesn't depend on the orientation
+        assert!(glm::distance(&stats.centroid, &Vec3::new(1.0, 1.0, 1.0)) < 1e-5);
+    }
+}
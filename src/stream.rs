@@ -0,0 +1,237 @@
+use crate::aabb::AABB;
+use crate::mesh::*;
+use crate::parser::{ParseError, Parser, Recovery, StlType};
+use anyhow::{bail, Result};
+use std::io::{self, BufReader, Cursor, Read};
+
+/// A stream whose first bytes have been read upfront to sniff its content
+/// Reading starts over at the first byte.
+pub struct Peeked<T: Read> {
+    prefix: Cursor<Vec<u8>>,
+    inner: T,
+}
+
+impl<T: Read> Peeked<T> {
+    pub fn new(mut inner: T, length: usize) -> io::Result<Self> {
+        let mut prefix = Vec::with_capacity(length);
+        (&mut inner).take(length as u64).read_to_end(&mut prefix)?;
+
+        Ok(Self {
+            prefix: Cursor::new(prefix),
+            inner,
+        })
+    }
+
+    /// The first bytes of the stream (less if the stream is shorter)
+    pub fn prefix(&self) -> &[u8] {
+        self.prefix.get_ref()
+    }
+}
+
+impl<T: Read> Read for Peeked<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.prefix.read(buf)? {
+            0 => self.inner.read(buf),
+            n => Ok(n),
+        }
+    }
+}
+
+/// STL reader for streams which can only be read once (e.g. stdin or pipes)
+/// The triangle count of binary files is checked at the end of the stream.
+pub struct StreamParser<T: Read> {
+    parser: Parser<Peeked<T>>,
+    started: bool,
+}
+
+impl<T: Read> StreamParser<T> {
+    /// The type can't be deduced from the size of a stream, it has to be sniffed beforehand
+    pub fn new(inner: Peeked<T>, stl_type: StlType, recalculate_normals: bool, recovery: Recovery) -> Result<Self> {
+        let parser = Parser::with_header(BufReader::new(inner), stl_type, None, recalculate_normals, recovery)?;
+
+        Ok(Self { parser, started: false })
+    }
+}
+
+impl<T: Read> MeshSource for StreamParser<T> {
+    // nothing to do as long as no triangle has been read
+    fn rewind(&mut self) -> Result<()> {
+        if self.started {
+            bail!("streams can't be rewound, buffer them to read them multiple times");
+        }
+        Ok(())
+    }
+
+    fn next_triangle(&mut self) -> Result<Option<Triangle>, ParseError> {
+        self.started = true;
+        self.parser.next_triangle()
+    }
+
+    fn triangle_count_hint(&self) -> Option<u64> {
+        self.parser.triangle_count_hint
+    }
+
+    fn is_multi_pass(&self) -> bool {
+        false
+    }
+
+    fn warnings(&self) -> &[String] {
+        &self.parser.warnings
+    }
+
+    fn part_names(&self) -> &[String] {
+        self.parser.part_names()
+    }
+
+    fn read_all_with_bounds(&mut self) -> Result<(Mesh, AABB)> {
+        self.rewind()?;
+
+        let mut triangles = vec![];
+        let mut aabb = AABB::empty();
+        while let Some(triangle) = self.next_triangle()? {
+            aabb.extend(&triangle);
+            triangles.push(triangle);
+        }
+
+        Ok((Mesh::new(triangles), aabb))
+    }
+}
+
+/// Makes a single-pass source multi-pass by keeping the triangles read so far in memory
+pub struct Buffered {
+    source: Box<dyn MeshSource>,
+    triangles: Vec<Triangle>,
+    next: usize,
+    done: bool,
+}
+
+impl Buffered {
+    pub fn new(source: Box<dyn MeshSource>) -> Self {
+        Self {
+            source,
+            triangles: vec![],
+            next: 0,
+            done: false,
+        }
+    }
+}
+
+impl MeshSource for Buffered {
+    fn rewind(&mut self) -> Result<()> {
+        self.next = 0;
+        Ok(())
+    }
+
+    fn next_triangle(&mut self) -> Result<Option<Triangle>, ParseError> {
+        if self.next == self.triangles.len() && !self.done {
+            match self.source.next_triangle()? {
+                Some(triangle) => self.triangles.push(triangle),
+                None => self.done = true,
+            }
+        }
+
+        let triangle = self.triangles.get(self.next).cloned();
+        if triangle.is_some() {
+            self.next += 1;
+        }
+        Ok(triangle)
+    }
+
+    fn triangle_count_hint(&self) -> Option<u64> {
+        self.source.triangle_count_hint()
+    }
+
+    fn warnings(&self) -> &[String] {
+        self.source.warnings()
+    }
+
+    fn part_names(&self) -> &[String] {
+        self.source.part_names()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mesh::*;
+    use crate::parser::{stl_type_of, ParseError, Parser, Recovery};
+    use crate::stream::{Buffered, Peeked, StreamParser};
+    use std::io::Cursor;
+
+    const TRI_BIN: &[u8] = include_bytes!("test_models/triangle.stl");
+    const TRI_ASCII: &[u8] = include_bytes!("test_models/triangle_ascii.stl");
+
+    // slices are Read but not Seek
+    fn stream(data: &[u8], recovery: Recovery) -> StreamParser<&[u8]> {
+        let inner = Peeked::new(data, 512).unwrap();
+        let stl_type = stl_type_of(inner.prefix(), None).unwrap();
+        StreamParser::new(inner, stl_type, false, recovery).unwrap()
+    }
+
+    #[test]
+    fn stream_bin_and_ascii() {
+        for data in &[TRI_BIN, TRI_ASCII] {
+            let mut parser = stream(data, Recovery::Strict);
+            assert!(!parser.is_multi_pass());
+
+            // same triangles as with the seekable parser
+            let mesh = parser.read_all().unwrap();
+            let expected = Parser::from_buf(Cursor::new(data), false).unwrap().read_all().unwrap();
+            assert_eq!(mesh.len(), expected.len());
+            assert_eq!(mesh[mesh.len() - 1], expected[expected.len() - 1]);
+
+            // the stream is consumed
+            assert!(parser.rewind().is_err());
+        }
+    }
+
+    #[test]
+    fn stream_bin_mismatch() {
+        let mut truncated = TRI_BIN.to_vec();
+        truncated[80] = 2;
+        let mut padded = TRI_BIN.to_vec();
+        padded.extend_from_slice(&[0; 3]);
+
+        let mut parser = stream(&truncated, Recovery::Strict);
+        assert!(parser.next_triangle().unwrap().is_some());
+        match parser.next_triangle() {
+            Err(ParseError::Truncated { triangles }) => assert_eq!(triangles, 1),
+            _ => panic!("expected a truncated stream"),
+        }
+
+        match stream(&padded, Recovery::Strict).read_all() {
+            Err(e) => assert!(matches!(
+                e.downcast_ref::<ParseError>(),
+                Some(ParseError::TrailingBytes { bytes: 3 })
+            )),
+            _ => panic!("expected trailing bytes"),
+        }
+
+        // lenient mode reports the mismatch at the end of the stream
+        let mut parser = stream(&truncated, Recovery::Lenient);
+        assert_eq!(parser.read_all().unwrap().len(), 1);
+        assert_eq!(parser.warnings().len(), 1);
+    }
+
+    #[test]
+    fn buffered_stream() {
+        let mut source = Buffered::new(Box::new(stream(TRI_BIN, Recovery::Strict)));
+        assert!(source.is_multi_pass());
+
+        let mesh = LazyMesh::new(&mut source);
+        assert_eq!((&mesh).into_iter().count(), 1);
+        assert_eq!((&mesh).into_iter().count(), 1);
+        assert!(mesh.take_error().is_none());
+    }
+
+    #[test]
+    fn lazy_stream() {
+        let mut source = stream(TRI_BIN, Recovery::Strict);
+
+        // the second pass can't rewind the stream
+        let mesh = LazyMesh::new(&mut source);
+        assert_eq!((&mesh).into_iter().count(), 1);
+        assert!(mesh.take_error().is_none());
+        assert_eq!((&mesh).into_iter().count(), 0);
+        assert!(matches!(mesh.take_error(), Some(ParseError::Io(_))));
+    }
+}
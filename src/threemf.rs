@@ -0,0 +1,516 @@
+use crate::compression::{read_limited, MAX_DECOMPRESSED_SIZE};
+use crate::mesh::*;
+use crate::parser::ParseError;
+use anyhow::{anyhow, bail, Result};
+use roxmltree::{Document, Node};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Seek};
+use zip::ZipArchive;
+
+const DEFAULT_MODEL_PATH: &str = "3D/3dmodel.model";
+const MODEL_RELATIONSHIP: &str = "http://schemas.microsoft.com/3dmanufacturing/2013/01/3dmodel";
+const MAX_COMPONENT_DEPTH: usize = 32;
+
+struct Component {
+    path: Option<String>,
+    object: u32,
+    transform: Mat4,
+}
+
+struct Object {
+    name: Option<String>,
+    vertices: Vec<Vec3>,
+    triangles: Vec<([usize; 3], Option<Vec3>)>,
+    components: Vec<Component>,
+    color: Option<Vec3>,
+}
+
+// a single *.model file of the package, converted to millimeters
+struct Model {
+    objects: HashMap<u32, Object>,
+    build: Vec<Component>,
+}
+
+/// 3MF reader
+/// Flattens the build items and their components into a single mesh,
+/// every build item becomes a part.
+pub struct ThreeMfParser;
+
+impl ThreeMfParser {
+    pub fn from_buf<T: Read + Seek>(inner: T) -> Result<InMemorySource> {
+        let mut archive = ZipArchive::new(inner)?;
+
+        let root_path = root_model_path(&mut archive)?;
+        let mut models = HashMap::new();
+
+        // the production extension moves objects into separate model files, which can reference further ones
+        let mut pending = vec![root_path.clone()];
+        while let Some(path) = pending.pop() {
+            if models.contains_key(&path) {
+                continue;
+            }
+
+            let model = parse_model(&read_entry(&mut archive, &path)?)?;
+            pending.extend(
+                model
+                    .objects
+                    .values()
+                    .flat_map(|o| &o.components)
+                    .chain(&model.build)
+                    .filter_map(|c| c.path.as_deref())
+                    .map(normalize_path)
+                    .filter(|p| !models.contains_key(p)),
+            );
+            models.insert(path, model);
+        }
+
+        let mut flattener = Flattener {
+            models: &models,
+            triangles: vec![],
+        };
+        let mut parts = vec![];
+
+        for item in &models[&root_path].build {
+            let path = item.path.as_deref().map_or(root_path.clone(), normalize_path);
+            let object = flattener.object(&path, item.object)?;
+            let name = object.name.clone().unwrap_or_else(|| format!("object {}", item.object));
+
+            flattener.flatten(&path, item.object, &item.transform, None, parts.len(), 0)?;
+            parts.push(name);
+        }
+
+        let triangles = flattener.triangles;
+        if parts.is_empty() {
+            parts.push(String::new());
+        }
+
+        Ok(InMemorySource::new(triangles, parts))
+    }
+}
+
+impl ThreeMfParser {
+    pub fn from_file(filename: &str) -> Result<InMemorySource> {
+        let file = fs::File::open(filename)?;
+        Self::from_buf(file)
+    }
+}
+
+struct Flattener<'a> {
+    models: &'a HashMap<String, Model>,
+    triangles: Vec<Triangle>,
+}
+
+impl<'a> Flattener<'a> {
+    fn object(&self, path: &str, id: u32) -> Result<&'a Object> {
+        self.models
+            .get(path)
+            .and_then(|model| model.objects.get(&id))
+            .ok_or_else(|| anyhow!("3MF: object {} not found in '{}'", id, path))
+    }
+
+    fn flatten(
+        &mut self,
+        path: &str,
+        id: u32,
+        transform: &Mat4,
+        color: Option<Vec3>,
+        part: usize,
+        depth: usize,
+    ) -> Result<()> {
+        if depth > MAX_COMPONENT_DEPTH {
+            bail!("3MF: components of object {} are nested too deeply", id);
+        }
+
+        let object = self.object(path, id)?;
+        let color = object.color.or(color);
+
+        // mirroring transforms flip the winding order
+        let mirrored = glm::mat4_to_mat3(transform).determinant() < 0.0;
+
+        for (indices, triangle_color) in &object.triangles {
+            let mut vertices = [
+                matmul(transform, &object.vertices[indices[0]]),
+                matmul(transform, &object.vertices[indices[1]]),
+                matmul(transform, &object.vertices[indices[2]]),
+            ];
+            if mirrored {
+                vertices.swap(1, 2);
+            }
+
+            let mut triangle = Triangle::new(vertices, Vec3::new(0.0, 0.0, 0.0));
+            triangle.recalculate_normal();
+            triangle.part = part as u32;
+            triangle.set_color(triangle_color.or(color));
+            if !triangle.has_finite_vertices() {
+                return Err(ParseError::NonFinite {
+                    triangle: self.triangles.len() as u64,
+                }
+                .into());
+            }
+            self.triangles.push(triangle);
+        }
+
+        for component in &object.components {
+            let component_path = component.path.as_deref().map_or(path.to_string(), normalize_path);
+            self.flatten(
+                &component_path,
+                component.object,
+                &(transform * component.transform),
+                color,
+                part,
+                depth + 1,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn read_entry<T: Read + Seek>(archive: &mut ZipArchive<T>, path: &str) -> Result<String> {
+    let entry = archive
+        .by_name(path)
+        .map_err(|_| anyhow!("3MF: '{}' is missing in the package", path))?;
+
+    let mut content = vec![];
+    read_limited(entry, &mut content, MAX_DECOMPRESSED_SIZE)?;
+
+    String::from_utf8(content).map_err(|_| anyhow!("3MF: '{}' is not valid utf8", path))
+}
+
+// the root model is referenced by the package relationships
+fn root_model_path<T: Read + Seek>(archive: &mut ZipArchive<T>) -> Result<String> {
+    let rels = match read_entry(archive, "_rels/.rels").ok() {
+        Some(rels) => rels,
+        None => return Ok(DEFAULT_MODEL_PATH.to_string()),
+    };
+
+    let doc = Document::parse(rels.trim_start_matches('\u{feff}'))?;
+    let target = doc
+        .descendants()
+        .filter(|n| n.tag_name().name() == "Relationship")
+        .find(|n| n.attribute("Type") == Some(MODEL_RELATIONSHIP))
+        .and_then(|n| n.attribute("Target"));
+
+    Ok(target.map_or(DEFAULT_MODEL_PATH.to_string(), normalize_path))
+}
+
+// zip entries are stored without the leading slash
+fn normalize_path(path: &str) -> String {
+    path.trim_start_matches('/').to_string()
+}
+
+fn parse_model(content: &str) -> Result<Model> {
+    let doc = Document::parse(content.trim_start_matches('\u{feff}'))?;
+    let root = doc.root_element();
+
+    let unit = match root.attribute("unit").unwrap_or("millimeter") {
+        "micron" => 0.001,
+        "millimeter" => 1.0,
+        "centimeter" => 10.0,
+        "inch" => 25.4,
+        "foot" => 304.8,
+        "meter" => 1000.0,
+        unit => bail!("3MF: unknown unit '{}'", unit),
+    };
+
+    let mut model = Model {
+        objects: HashMap::new(),
+        build: vec![],
+    };
+
+    // base materials and color groups are both indexed lists of colors
+    let mut materials: HashMap<u32, Vec<Option<Vec3>>> = HashMap::new();
+
+    for resources in children(root, "resources") {
+        for group in resources.children().filter(|n| n.is_element()) {
+            let colors = match group.tag_name().name() {
+                "basematerials" => children(group, "base")
+                    .map(|base| attribute(base, "displaycolor").and_then(parse_color))
+                    .collect::<Vec<_>>(),
+                "colorgroup" => children(group, "color")
+                    .map(|color| attribute(color, "color").and_then(parse_color))
+                    .collect::<Vec<_>>(),
+                _ => continue,
+            };
+
+            materials.insert(parse_attribute(group, "id")?, colors);
+        }
+
+        for node in children(resources, "object") {
+            let id = parse_attribute(node, "id")?;
+            let object = parse_object(node, &materials, unit)?;
+            model.objects.insert(id, object);
+        }
+    }
+
+    for build in children(root, "build") {
+        for item in children(build, "item") {
+            model.build.push(parse_component(item, unit)?);
+        }
+    }
+
+    Ok(model)
+}
+
+fn parse_object(node: Node, materials: &HashMap<u32, Vec<Option<Vec3>>>, unit: f32) -> Result<Object> {
+    // unknown materials and unparsable colors fall back to the model color
+    let material = |pid: Option<u32>, index: Option<usize>| -> Option<Vec3> { *materials.get(&pid?)?.get(index?)? };
+
+    let pid = attribute(node, "pid").and_then(|v| v.parse::<u32>().ok());
+    let pindex = attribute(node, "pindex").and_then(|v| v.parse::<usize>().ok());
+
+    let mut object = Object {
+        name: attribute(node, "name").map(|n| n.to_string()),
+        vertices: vec![],
+        triangles: vec![],
+        components: vec![],
+        color: material(pid, pindex),
+    };
+
+    for mesh in children(node, "mesh") {
+        for vertex in children(mesh, "vertices").flat_map(|n| children(n, "vertex")) {
+            object.vertices.push(
+                Vec3::new(
+                    parse_attribute(vertex, "x")?,
+                    parse_attribute(vertex, "y")?,
+                    parse_attribute(vertex, "z")?,
+                ) * unit,
+            );
+        }
+
+        for triangle in children(mesh, "triangles").flat_map(|n| children(n, "triangle")) {
+            let indices = [
+                parse_attribute(triangle, "v1")?,
+                parse_attribute(triangle, "v2")?,
+                parse_attribute(triangle, "v3")?,
+            ];
+            if indices.iter().any(|i| *i >= object.vertices.len()) {
+                bail!("3MF: vertex index out of range in {:?}", indices);
+            }
+
+            // triangles can override the material of the object
+            let triangle_pid = attribute(triangle, "pid").and_then(|v| v.parse::<u32>().ok());
+            let p1 = attribute(triangle, "p1").and_then(|v| v.parse::<usize>().ok());
+            let color = material(triangle_pid.or(pid), p1.or(pindex));
+
+            object.triangles.push((indices, color));
+        }
+    }
+
+    for component in children(node, "components").flat_map(|n| children(n, "component")) {
+        object.components.push(parse_component(component, unit)?);
+    }
+
+    Ok(object)
+}
+
+// components and build items share the same attributes, their translation is given in the unit of the model
+fn parse_component(node: Node, unit: f32) -> Result<Component> {
+    let mut transform = attribute(node, "transform").map_or(Ok(Mat4::identity()), parse_transform)?;
+    for i in 0..3 {
+        transform[(i, 3)] *= unit;
+    }
+
+    Ok(Component {
+        path: attribute(node, "path").map(|p| p.to_string()),
+        object: parse_attribute(node, "objectid")?,
+        transform,
+    })
+}
+
+// 3MF uses row vectors, the 12 values are the first three columns of a 4x4 matrix
+fn parse_transform(value: &str) -> Result<Mat4> {
+    let m = value
+        .split_whitespace()
+        .map(|v| v.parse::<f32>())
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|_| anyhow!("3MF: invalid transform '{}'", value))?;
+
+    if m.len() != 12 {
+        bail!("3MF: invalid transform '{}'", value);
+    }
+
+    Ok(Mat4::new(
+        m[0], m[3], m[6], m[9], //
+        m[1], m[4], m[7], m[10], //
+        m[2], m[5], m[8], m[11], //
+        0.0, 0.0, 0.0, 1.0,
+    ))
+}
+
+// "#RRGGBB" or "#RRGGBBAA"
+fn parse_color(value: &str) -> Option<Vec3> {
+    let hex = value.strip_prefix('#')?;
+    if hex.len() != 6 && hex.len() != 8 {
+        return None;
+    }
+
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok().map(f32::from);
+    Some(Vec3::new(channel(0)?, channel(2)?, channel(4)?) / 255.0)
+}
+
+fn children<'a, 'input: 'a>(node: Node<'a, 'input>, name: &'a str) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(move |n| n.tag_name().name() == name)
+}
+
+// looks up an attribute by its local name regardless of the namespace (e.g. 'p:path')
+fn attribute<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.attributes().iter().find(|a| a.name() == name).map(|a| a.value())
+}
+
+fn parse_attribute<T: std::str::FromStr>(node: Node, name: &str) -> Result<T> {
+    let value = attribute(node, name)
+        .ok_or_else(|| anyhow!("3MF: <{}> is missing the '{}' attribute", node.tag_name().name(), name))?;
+
+    value
+        .parse::<T>()
+        .map_err(|_| anyhow!("3MF: invalid value '{}' for '{}'", value, name))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mesh::*;
+    use crate::threemf::ThreeMfParser;
+    use std::io::{Cursor, Write};
+    use zip::write::{FileOptions, ZipWriter};
+
+    const RELS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+  <Relationship Target="/3D/model.model" Id="rel0" Type="http://schemas.microsoft.com/3dmanufacturing/2013/01/3dmodel"/>
+</Relationships>"#;
+
+    const MODEL: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<model unit="centimeter" xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02"
+       xmlns:p="http://schemas.microsoft.com/3dmanufacturing/production/2015/06">
+  <resources>
+    <basematerials id="1">
+      <base name="red" displaycolor="#FF0000" />
+      <base name="blue" displaycolor="#0000FFFF" />
+    </basematerials>
+    <object id="2" type="model" pid="1" pindex="0">
+      <mesh>
+        <vertices>
+          <vertex x="0" y="0" z="0" />
+          <vertex x="1" y="0" z="0" />
+          <vertex x="0" y="1" z="0" />
+        </vertices>
+        <triangles>
+          <triangle v1="0" v2="1" v3="2" />
+          <triangle v1="0" v2="2" v3="1" p1="1" />
+        </triangles>
+      </mesh>
+    </object>
+    <object id="3" name="assembly" type="model">
+      <components>
+        <component objectid="2" transform="1 0 0 0 1 0 0 0 1 0 0 1" />
+        <component objectid="4" p:path="/3D/Objects/part.model" />
+      </components>
+    </object>
+  </resources>
+  <build>
+    <item objectid="3" transform="1 0 0 0 1 0 0 0 1 5 0 0" />
+    <item objectid="2" transform="-1 0 0 0 1 0 0 0 1 0 0 0" />
+  </build>
+</model>"##;
+
+    const PART_MODEL: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<model unit="millimeter" xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02"
+       xmlns:p="http://schemas.microsoft.com/3dmanufacturing/production/2015/06">
+  <resources>
+    <object id="4" type="model">
+      <mesh>
+        <vertices>
+          <vertex x="0" y="0" z="20" />
+          <vertex x="10" y="0" z="20" />
+          <vertex x="0" y="10" z="20" />
+        </vertices>
+        <triangles>
+          <triangle v1="0" v2="1" v3="2" />
+        </triangles>
+      </mesh>
+      <components>
+        <component objectid="5" p:path="/3D/Objects/nested.model" transform="1 0 0 0 1 0 0 0 1 0 0 10" />
+      </components>
+    </object>
+  </resources>
+  <build />
+</model>"#;
+
+    const NESTED_MODEL: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<model unit="meter" xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02">
+  <resources>
+    <object id="5" type="model">
+      <mesh>
+        <vertices>
+          <vertex x="0" y="0" z="0.001" />
+          <vertex x="0.01" y="0" z="0.001" />
+          <vertex x="0" y="0.01" z="0.001" />
+        </vertices>
+        <triangles>
+          <triangle v1="0" v2="1" v3="2" />
+        </triangles>
+      </mesh>
+    </object>
+  </resources>
+  <build />
+</model>"#;
+
+    fn package() -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        for (path, content) in [
+            ("_rels/.rels", RELS),
+            ("3D/model.model", MODEL),
+            ("3D/Objects/part.model", PART_MODEL),
+            ("3D/Objects/nested.model", NESTED_MODEL),
+        ]
+        .iter()
+        {
+            zip.start_file(*path, FileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn threemf_build_items() {
+        let mut parser = ThreeMfParser::from_buf(Cursor::new(package())).unwrap();
+        let mesh = parser.read_all().unwrap();
+
+        assert_eq!(parser.part_names(), &["assembly".to_string(), "object 2".to_string()]);
+        assert_eq!(mesh.len(), 6);
+
+        // item and component transform, centimeters are scaled to millimeters
+        assert_eq!(mesh[0].part, 0);
+        assert_eq!(mesh[0].vertices[0], Vec3::new(50.0, 0.0, 10.0));
+        assert_eq!(mesh[0].vertices[1], Vec3::new(60.0, 0.0, 10.0));
+        assert_eq!(mesh[0].normal, Vec3::new(0.0, 0.0, 1.0));
+
+        // object and triangle materials
+        assert_eq!(mesh[0].color(), Some(Vec3::new(1.0, 0.0, 0.0)));
+        assert_eq!(mesh[1].color(), Some(Vec3::new(0.0, 0.0, 1.0)));
+
+        // component from the production extension without a material, each model file has its own unit
+        assert_eq!(mesh[2].vertices[0], Vec3::new(50.0, 0.0, 20.0));
+        assert_eq!(mesh[2].color(), None);
+
+        // component of a component in yet another model file
+        assert!((mesh[3].vertices[0] - Vec3::new(50.0, 0.0, 11.0)).norm() < 1e-4);
+        assert!((mesh[3].vertices[1] - Vec3::new(60.0, 0.0, 11.0)).norm() < 1e-4);
+
+        // mirrored item keeps its normal pointing outwards
+        assert_eq!(mesh[4].part, 1);
+        assert_eq!(mesh[4].normal, Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(mesh[4].vertices[2], Vec3::new(-10.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn threemf_missing_model() {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file("_rels/.rels", FileOptions::default()).unwrap();
+        zip.write_all(RELS.as_bytes()).unwrap();
+        let package = zip.finish().unwrap().into_inner();
+
+        assert!(ThreeMfParser::from_buf(Cursor::new(package)).is_err());
+    }
+}
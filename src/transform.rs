@@ -0,0 +1,422 @@
+use crate::mesh::*;
+use anyhow::{bail, Context, Result};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    pub fn unit(self) -> Vec3 {
+        match self {
+            Axis::X => Vec3::new(1.0, 0.0, 0.0),
+            Axis::Y => Vec3::new(0.0, 1.0, 0.0),
+            Axis::Z => Vec3::new(0.0, 0.0, 1.0),
+        }
+    }
+}
+
+impl FromStr for Axis {
+    type Err = anyhow::Error;
+
+    fn from_str(axis: &str) -> Result<Self> {
+        match axis.trim().to_lowercase().as_str() {
+            "x" => Ok(Axis::X),
+            "y" => Ok(Axis::Y),
+            "z" => Ok(Axis::Z),
+            _ => bail!("invalid axis '{}', expected x, y or z", axis),
+        }
+    }
+}
+
+/// An affine transformation of a model
+/// Normals are transformed by the inverse transpose, hence they stay perpendicular to their triangles
+/// under non-uniform scaling. Mirroring reverses the winding of the triangles, the vertices are
+/// reordered such that winding and normals stay consistent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub matrix: Mat4,
+    normal_matrix: glm::Mat3,
+    mirrors: bool,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::rigid(Mat4::identity())
+    }
+}
+
+impl Transform {
+    /// Fails if the matrix isn't finite or collapses the model (e.g. a scale of 0), its normals would
+    /// be undefined
+    pub fn new(matrix: Mat4) -> Result<Self> {
+        let linear = glm::mat4_to_mat3(&matrix);
+        let inverse = match linear.try_inverse() {
+            Some(inverse) if matrix.iter().chain(inverse.iter()).all(|c| c.is_finite()) => inverse,
+            _ => bail!("the transform collapses the model or isn't finite"),
+        };
+
+        Ok(Self {
+            matrix,
+            normal_matrix: inverse.transpose(),
+            mirrors: linear.determinant() < 0.0,
+        })
+    }
+
+    // rotations, translations and mirrors, the inverse transpose of their linear part is the part itself
+    fn rigid(matrix: Mat4) -> Self {
+        let linear = glm::mat4_to_mat3(&matrix);
+
+        Self {
+            matrix,
+            normal_matrix: linear,
+            mirrors: linear.determinant() < 0.0,
+        }
+    }
+
+    /// Counterclockwise rotation around the axis looking from the positive side towards the origin
+    pub fn rotation(axis: Axis, degrees: f32) -> Self {
+        Self::rigid(glm::rotation(degrees.to_radians(), &axis.unit()))
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        Self::rigid(glm::translation(&offset))
+    }
+
+    /// Fails for factors of 0 and non-finite ones
+    pub fn scaling(factors: Vec3) -> Result<Self> {
+        Self::new(glm::scaling(&factors))
+    }
+
+    pub fn mirror(axis: Axis) -> Self {
+        Self::rigid(glm::scaling(&(Vec3::repeat(1.0) - 2.0 * axis.unit())))
+    }
+
+    /// Rotates the up axis of a model onto the z axis, the up axis of the renderer
+    pub fn up_axis(axis: Axis) -> Self {
+        match axis {
+            Axis::X => Self::rotation(Axis::Y, -90.0),
+            Axis::Y => Self::rotation(Axis::X, 90.0),
+            Axis::Z => Self::default(),
+        }
+    }
+
+    /// The shortest rotation turning one direction onto another
+    pub fn alignment(from: Vec3, to: Vec3) -> Self {
+        let (from, to) = (from.normalize(), to.normalize());
+        let axis = from.cross(&to);
+        let cos = from.dot(&to);
+
+        if axis.norm() > 1e-6 {
+            Self::rigid(glm::rotation(axis.norm().atan2(cos), &axis.normalize()))
+        } else if cos > 0.0 {
+            Self::default()
+        } else {
+            // opposite directions, any perpendicular axis does
+            let other = if from.x.abs() < 0.9 { Axis::X } else { Axis::Y };
+            Self::rigid(glm::rotation(
+                std::f32::consts::PI,
+                &from.cross(&other.unit()).normalize(),
+            ))
+        }
+    }
+
+    /// Applies the other transform after this one
+    pub fn then(&self, other: &Transform) -> Self {
+        Self {
+            matrix: other.matrix * self.matrix,
+            normal_matrix: other.normal_matrix * self.normal_matrix,
+            mirrors: self.mirrors != other.mirrors,
+        }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.matrix == Mat4::identity()
+    }
+
+    /// The identity leaves the triangle untouched
+    pub fn apply(&self, triangle: &mut Triangle) {
+        if self.is_identity() {
+            return;
+        }
+
+        triangle.vertices = triangle.vertices.map(|v| matmul(&self.matrix, &v));
+        triangle.normal = self.transform_normal(&triangle.normal);
+        if self.mirrors {
+            triangle.vertices.swap(1, 2);
+        }
+
+        if triangle.attributes.is_some() {
+            let mut colors = triangle.vertex_colors();
+            let mut normals = triangle.vertex_normals().map(|n| n.map(|n| self.transform_normal(&n)));
+            if self.mirrors {
+                colors = colors.map(|[a, b, c]| [a, c, b]);
+                normals = normals.map(|[a, b, c]| [a, c, b]);
+            }
+
+            triangle.set_vertex_colors(colors);
+            triangle.set_vertex_normals(normals);
+        }
+    }
+
+    pub fn apply_mesh(&self, mesh: &mut Mesh) {
+        for triangle in mesh.iter_mut() {
+            self.apply(triangle);
+        }
+    }
+
+    // zero normals (e.g. of degenerate triangles) stay zero
+    fn transform_normal(&self, normal: &Vec3) -> Vec3 {
+        let transformed = self.normal_matrix * normal;
+        if transformed.norm() > 0.0 {
+            transformed.normalize()
+        } else {
+            transformed
+        }
+    }
+
+    /// Parses rotations like 'x=90,z=45', applied in the given order
+    pub fn parse_rotation(spec: &str) -> Result<Self> {
+        Ok(parse_axis_values(spec)?
+            .into_iter()
+            .fold(Self::default(), |t, (axis, degrees)| {
+                t.then(&Self::rotation(axis, degrees))
+            }))
+    }
+
+    /// Parses translations like 'x=10,y=-5'
+    pub fn parse_translation(spec: &str) -> Result<Self> {
+        let mut offset = Vec3::zeros();
+        for (axis, value) in parse_axis_values(spec)? {
+            offset += axis.unit() * value;
+        }
+
+        Ok(Self::translation(offset))
+    }
+
+    /// Parses a uniform scale like '2' or a per axis one like 'x=2,z=0.5'
+    /// A factor of 0 would collapse the model, it is rejected.
+    pub fn parse_scaling(spec: &str) -> Result<Self> {
+        let factors = match spec.trim().parse::<f32>() {
+            Ok(factor) => vec![(None, factor)],
+            Err(_) => parse_axis_values(spec)?
+                .into_iter()
+                .map(|(axis, value)| (Some(axis), value))
+                .collect(),
+        };
+
+        let mut scale = Vec3::repeat(1.0);
+        for (axis, factor) in factors {
+            if factor == 0.0 || !factor.is_finite() {
+                bail!(
+                    "invalid scale factor {} in '{}', expected a finite number other than 0",
+                    factor,
+                    spec
+                );
+            }
+
+            match axis {
+                Some(axis) => scale.component_mul_assign(&(Vec3::repeat(1.0) + axis.unit() * (factor - 1.0))),
+                None => scale *= factor,
+            }
+        }
+
+        Self::scaling(scale)
+    }
+
+    /// Parses the mirrored axes like 'y' or 'x,z'
+    pub fn parse_mirror(spec: &str) -> Result<Self> {
+        spec.split(',')
+            .map(|axis| axis.parse::<Axis>())
+            .try_fold(Self::default(), |t, axis| Ok(t.then(&Self::mirror(axis?))))
+    }
+}
+
+// 'x=90,z=45' to the axes and their values
+fn parse_axis_values(spec: &str) -> Result<Vec<(Axis, f32)>> {
+    spec.split(',')
+        .map(|pair| {
+            let mut tokens = pair.splitn(2, '=');
+            match (tokens.next(), tokens.next()) {
+                (Some(axis), Some(value)) => {
+                    let value: f32 = value
+                        .trim()
+                        .parse()
+                        .with_context(|| format!("invalid value in '{}'", pair))?;
+                    if !value.is_finite() {
+                        bail!("invalid value in '{}', expected a finite number", pair);
+                    }
+                    Ok((axis.parse()?, value))
+                }
+                _ => bail!("expected 'axis=value' instead of '{}'", pair),
+            }
+        })
+        .collect()
+}
+
+/// A model transformed lazily while iterating its triangles
+#[derive(Debug, Clone, Copy)]
+pub struct Transformed<M> {
+    mesh: M,
+    transform: Transform,
+}
+
+impl<M: IntoIterator<Item = Triangle> + Copy> Transformed<M> {
+    pub fn new(mesh: M, transform: Transform) -> Self {
+        Self { mesh, transform }
+    }
+}
+
+impl<M: IntoIterator<Item = Triangle> + Copy> IntoIterator for Transformed<M> {
+    type Item = Triangle;
+    type IntoIter = TransformedIter<M::IntoIter>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        TransformedIter {
+            inner: self.mesh.into_iter(),
+            transform: self.transform,
+        }
+    }
+}
+
+pub struct TransformedIter<I> {
+    inner: I,
+    transform: Transform,
+}
+
+impl<I: Iterator<Item = Triangle>> Iterator for TransformedIter<I> {
+    type Item = Triangle;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut triangle = self.inner.next()?;
+        self.transform.apply(&mut triangle);
+        Some(triangle)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mesh::*;
+    use crate::transform::{Axis, Transform, Transformed};
+
+    // counterclockwise seen from above, tilted by 45° around the x axis
+    fn tilted() -> Triangle {
+        let mut triangle = Triangle::new(
+            [
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 1.0),
+            ],
+            Vec3::zeros(),
+        );
+        triangle.recalculate_normal();
+        triangle
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!(glm::distance(&a, &b) < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    // the normal agrees with the winding
+    fn assert_consistent(triangle: &Triangle) {
+        let mut recalculated = triangle.clone();
+        recalculated.recalculate_normal();
+        assert_close(triangle.normal, recalculated.normal);
+    }
+
+    #[test]
+    fn rotate() {
+        let mut triangle = tilted();
+        Transform::parse_rotation("x=90").unwrap().apply(&mut triangle);
+
+        assert_close(triangle.vertices[2], Vec3::new(0.0, -1.0, 1.0));
+        assert_close(triangle.normal, Vec3::new(0.0, -1.0, -1.0).normalize());
+        assert_consistent(&triangle);
+
+        // rotations are applied in the given order
+        let combined = Transform::parse_rotation("x=90,z=90").unwrap();
+        let expected = Transform::rotation(Axis::X, 90.0).then(&Transform::rotation(Axis::Z, 90.0));
+        assert_eq!(combined, expected);
+        assert!(Transform::parse_rotation("w=90").is_err());
+        assert!(Transform::parse_rotation("x").is_err());
+    }
+
+    #[test]
+    fn scale_non_uniformly() {
+        let mut triangle = tilted();
+        Transform::parse_scaling("z=2").unwrap().apply(&mut triangle);
+
+        assert_close(triangle.vertices[2], Vec3::new(0.0, 1.0, 2.0));
+        assert_consistent(&triangle);
+
+        let mut triangle = tilted();
+        Transform::parse_scaling("3").unwrap().apply(&mut triangle);
+        assert_close(triangle.vertices[1], Vec3::new(3.0, 0.0, 0.0));
+        assert_close(triangle.normal, tilted().normal);
+    }
+
+    #[test]
+    fn collapsing_scale() {
+        for spec in ["0", "y=0", "x=2,z=0", "nan", "x=inf", "-inf"] {
+            assert!(Transform::parse_scaling(spec).is_err(), "{}", spec);
+        }
+        assert!(Transform::parse_translation("x=nan").is_err());
+
+        let flat = glm::scaling(&Vec3::new(1.0, 1.0, 0.0));
+        assert!(Transform::new(flat).is_err());
+        assert!(Transform::new(Mat4::identity()).unwrap().is_identity());
+    }
+
+    #[test]
+    fn mirror() {
+        let mut triangle = tilted();
+        triangle.set_vertex_colors(Some([Vec3::zeros(), Vec3::repeat(1.0), Vec3::repeat(2.0)]));
+        Transform::parse_mirror("y").unwrap().apply(&mut triangle);
+
+        // the winding is restored
+        assert_eq!(triangle.vertices[1], Vec3::new(0.0, -1.0, 1.0));
+        assert_eq!(triangle.vertex_colors().unwrap()[1], Vec3::repeat(2.0));
+        assert_close(triangle.normal, Vec3::new(0.0, 1.0, 1.0).normalize());
+        assert_consistent(&triangle);
+
+        // mirroring twice doesn't reorder
+        assert!(!Transform::parse_mirror("x,y").unwrap().mirrors);
+    }
+
+    #[test]
+    fn up_axis() {
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            let up = matmul(&Transform::up_axis(axis).matrix, &axis.unit());
+            assert_close(up, Vec3::new(0.0, 0.0, 1.0));
+        }
+    }
+
+    #[test]
+    fn alignment() {
+        let down = Vec3::new(0.0, 0.0, -1.0);
+        for from in [
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -2.0),
+            Vec3::new(1.0, 0.0, 0.0),
+        ] {
+            let aligned = matmul(&Transform::alignment(from, down).matrix, &from.normalize());
+            assert_close(aligned, down);
+        }
+        assert!(Transform::alignment(down, down).is_identity());
+    }
+
+    #[test]
+    fn transform_lazily() {
+        let mesh = Mesh::new(vec![tilted(), tilted()]);
+        let translation = Transform::parse_translation("x=1,z=-2").unwrap();
+
+        for triangle in Transformed::new(&mesh, translation) {
+            assert_eq!(triangle.vertices[0], Vec3::new(1.0, 0.0, -2.0));
+            assert_close(triangle.normal, tilted().normal);
+        }
+    }
+}